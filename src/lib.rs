@@ -1,331 +1,3381 @@
-use std::fs::read_to_string;
+use std::fs::File;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use core::str::FromStr;
-use std::fs::read;
+use core::cmp::Ordering;
 use core::ops::Not;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use log::{debug, info, warn};
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+#[cfg(feature = "download")]
+use std::sync::Mutex;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+/// The version of the file format written by this crate. Files without a version
+/// are treated as version 0.
+pub const CURRENT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct TopLevel {
+    #[serde(default)]
+    pub version: u32,
     pub benchmark_sets: BTreeMap<String, BenchmarkSet>,
     pub meta_sets: BTreeMap<String, BTreeSet<String>>,
+    /// Keys this crate doesn't know about, kept so that they survive a load-modify-write
+    /// cycle. This is a compatibility escape hatch for prototyping new fields or
+    /// reading files written by a newer version, not a place for data this crate
+    /// should understand.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for TopLevel {
+    fn default() -> TopLevel {
+        TopLevel {
+            version: CURRENT_VERSION,
+            benchmark_sets: BTreeMap::new(),
+            meta_sets: BTreeMap::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+/// Returns a JSON Schema describing the procedure file format, for editor completion
+/// and validation.
+#[cfg(feature = "schemars")]
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(TopLevel);
+    serde_json::to_string_pretty(&schema).expect("a schema always serializes")
+}
+
+/// Upgrades a top level from an older file format version to `CURRENT_VERSION`. Every
+/// loader runs this, so callers only need it for a `TopLevel` built some other way.
+/// No version so far has changed the layout, so this only updates the version.
+pub fn migrate(mut top_level: TopLevel) -> TopLevel {
+    if top_level.version < CURRENT_VERSION {
+        top_level.version = CURRENT_VERSION;
+    }
+    top_level
+}
+
+/// What a name refers to within a `TopLevel`, as returned by `TopLevel::classify`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NameKind {
+    Benchmark,
+    Meta,
+    /// The name is both a benchmark set and a meta set. Benchmark sets and meta sets
+    /// have separate namespaces, so this is allowed, and when such a name appears as
+    /// a meta set member the meta walk expands the meta set and also includes the
+    /// benchmark set. Writing to it needs care to pick the intended kind.
+    Both,
+    Unknown,
+}
+
+/// The names of the sets in a `TopLevel`, filtered by `ProcedureKind`. Sets of a
+/// kind that was not asked for are left empty. `descriptions` holds the
+/// description of each listed benchmark set that has one.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Summary {
+    pub benchmark_sets: Vec<String>,
+    pub meta_sets: Vec<String>,
+    pub descriptions: BTreeMap<String, String>,
 }
 
 impl TopLevel {
+    pub fn summary(&self, kinds: ProcedureKind) -> Summary {
+        let mut summary = Summary::default();
+        if kinds == ProcedureKind::Benchmark || kinds == ProcedureKind::Both {
+            summary.benchmark_sets = self.benchmark_sets.keys().cloned().collect();
+            summary.descriptions = self
+                .benchmark_sets
+                .iter()
+                .filter_map(|(name, set)| Some((name.clone(), set.description.clone()?)))
+                .collect();
+        }
+        if kinds == ProcedureKind::Meta || kinds == ProcedureKind::Both {
+            summary.meta_sets = self.meta_sets.keys().cloned().collect();
+        }
+        summary
+    }
+
     pub fn print_summary(&self, kinds: ProcedureKind) {
+        let summary = self.summary(kinds.clone());
         if kinds == ProcedureKind::Benchmark || kinds == ProcedureKind::Both {
             println!("    Benchmark Sets:");
-            for set in self.benchmark_sets.keys() {
-                println!("\t{:?}", set);
+            for set in &summary.benchmark_sets {
+                match summary.descriptions.get(set) {
+                    Some(description) => println!("\t{:?} - {}", set, description),
+                    None => println!("\t{:?}", set),
+                }
             }
         }
         if kinds == ProcedureKind::Meta || kinds == ProcedureKind::Both {
             println!("    Meta Sets:");
-            for set in self.meta_sets.keys() {
+            for set in &summary.meta_sets {
                 println!("\t{:?}", set);
             }
         }
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct BenchmarkSet {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub save_subdirectory: Option<PathBuf>,
-    pub mods: BTreeSet<Mod>,
-    pub maps: BTreeSet<Map>,
-    pub ticks: u32,
-    pub runs: u32,
-}
+    /// Says whether a name refers to a benchmark set, a meta set, both, or nothing.
+    pub fn classify(&self, name: &str) -> NameKind {
+        match (self.benchmark_sets.contains_key(name), self.meta_sets.contains_key(name)) {
+            (true, true) => NameKind::Both,
+            (true, false) => NameKind::Benchmark,
+            (false, true) => NameKind::Meta,
+            (false, false) => NameKind::Unknown,
+        }
+    }
 
-impl Default for BenchmarkSet {
-    fn default() -> BenchmarkSet {
-        BenchmarkSet {
-            save_subdirectory: None,
-            mods: BTreeSet::new(),
-            maps: BTreeSet::new(),
-            ticks: 0,
-            runs: 0,
+    pub fn benchmark_set_count(&self) -> usize {
+        self.benchmark_sets.len()
+    }
+
+    pub fn meta_set_count(&self) -> usize {
+        self.meta_sets.len()
+    }
+
+    /// True if there are neither benchmark sets nor meta sets.
+    pub fn is_empty(&self) -> bool {
+        self.benchmark_sets.is_empty() && self.meta_sets.is_empty()
+    }
+
+    /// Iterates over the benchmark sets in sorted name order.
+    pub fn iter_benchmark_sets(&self) -> impl Iterator<Item = (&String, &BenchmarkSet)> {
+        self.benchmark_sets.iter()
+    }
+
+    /// Inserts a benchmark set, returning the set it replaced if there was one.
+    /// Returns Err if a set with this name exists and overwrite is false.
+    pub fn insert_benchmark_set(
+        &mut self,
+        name: &str,
+        set: BenchmarkSet,
+        overwrite: ProcedureOverwrite,
+    ) -> Result<Option<BenchmarkSet>, ProcedureError> {
+        if self.benchmark_sets.contains_key(name) && overwrite == false.into() {
+            return Err(ProcedureError::ProcedureAlreadyExists);
         }
+        Ok(self.benchmark_sets.insert(name.to_string(), set))
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Clone, Ord, Eq, PartialOrd)]
-pub struct Map {
-    pub name: String,
-    #[serde(skip)]
-    pub path: PathBuf,
-    pub sha256: String,
-    pub download_link: String,
-}
+    /// Inserts a meta set, returning the members it replaced if there were any.
+    /// Returns Err if a meta set with this name exists and overwrite is false.
+    pub fn insert_meta(
+        &mut self,
+        name: &str,
+        members: BTreeSet<String>,
+        overwrite: ProcedureOverwrite,
+    ) -> Result<Option<BTreeSet<String>>, ProcedureError> {
+        if self.meta_sets.contains_key(name) && overwrite == false.into() {
+            return Err(ProcedureError::ProcedureAlreadyExists);
+        }
+        Ok(self.meta_sets.insert(name.to_string(), members))
+    }
 
-impl Map {
-    pub fn new(path: &PathBuf, sha256: &str, download_link: &str) -> Map {
-        Map {
-            name: path.file_name().unwrap().to_string_lossy().to_string(),
-            path: path.to_path_buf(),
-            sha256: sha256.to_string(),
-            download_link: download_link.to_string(),
+    /// Removes a benchmark set, returning it if it was present. Meta sets which
+    /// listed it are left untouched.
+    pub fn remove_benchmark_set(&mut self, name: &str) -> Option<BenchmarkSet> {
+        self.benchmark_sets.remove(name)
+    }
+
+    /// Removes a meta set, returning its members if it was present. The name is
+    /// also scrubbed from the members of every other meta set that listed it
+    /// directly, unless a benchmark set shares the name, since those references
+    /// still resolve to that benchmark set.
+    pub fn remove_meta(&mut self, name: &str) -> Option<BTreeSet<String>> {
+        let removed = self.meta_sets.remove(name)?;
+        if !self.benchmark_sets.contains_key(name) {
+            for members in self.meta_sets.values_mut() {
+                members.remove(name);
+            }
         }
+        Some(removed)
     }
-}
 
-impl PartialEq for Map {
-    fn eq(&self, cmp: &Self) -> bool {
-        if self.sha256 == cmp.sha256 {
-            return true;
+    /// Returns all benchmark sets contained within this meta set, as well as any found
+    /// recursively within meta sets it contains. Returns an empty map if the key is
+    /// not present.
+    pub fn resolve_meta(&self, key: &str) -> HashMap<String, BenchmarkSet> {
+        walk_meta_for_benchmarks(key, self)
+    }
+
+    /// Checks every benchmark set for malformed data, collecting all problems found
+    /// rather than stopping at the first. Map hashes must be lowercase hex of the
+    /// length their `hash_algo` produces and mod hashes 40 lowercase hex characters. An empty mod hash is
+    /// allowed, as it means no hash was recorded. Mod names must be non-empty and use
+    /// only ASCII letters, digits, `_` and `-`, as the mod portal requires, which
+    /// catches a display name stored in place of the internal name. Ticks and runs must
+    /// be at least 1, and a Factorio version, if given, must be a dotted numeric version.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        self.validate_with(&ValidationOptions::default())
+    }
+
+    /// Same as `validate`, but with configurable minimums for ticks and runs.
+    pub fn validate_with(&self, options: &ValidationOptions) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        for (set_name, set) in &self.benchmark_sets {
+            let mut push = |item: ItemRef, problem: String| {
+                errors.push(ValidationError {
+                    set_name: set_name.to_string(),
+                    item,
+                    problem,
+                })
+            };
+            if set.ticks < options.min_ticks {
+                push(
+                    ItemRef::Set,
+                    format!("ticks is {} but must be at least {}", set.ticks, options.min_ticks),
+                );
+            }
+            if set.runs < options.min_runs {
+                push(
+                    ItemRef::Set,
+                    format!("runs is {} but must be at least {}", set.runs, options.min_runs),
+                );
+            }
+            if let Some(version) = &set.factorio_version {
+                if parse_version(version).is_none() {
+                    push(
+                        ItemRef::Set,
+                        format!("factorio_version {:?} is not a dotted version", version),
+                    );
+                }
+            }
+            for map in &set.maps {
+                if !is_hex_digest(&map.sha256, map.hash_algo.hex_len()) {
+                    push(
+                        ItemRef::Map(map.name.to_string()),
+                        format!("has an invalid hash {:?}", map.sha256),
+                    );
+                }
+            }
+            for m in &set.mods {
+                if !is_valid_mod_name(&m.name) {
+                    push(
+                        ItemRef::Mod(m.name.to_string()),
+                        "is not a valid mod name, which may only use letters, digits, _ and -".to_string(),
+                    );
+                }
+                if !m.sha1.is_empty() && !is_hex_digest(&m.sha1, 40) {
+                    push(
+                        ItemRef::Mod(m.name.to_string()),
+                        format!("has an invalid sha1 {:?}", m.sha1),
+                    );
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
-        false
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialOrd, Ord, Eq)]
-pub struct Mod {
-    pub name: String,
-    #[serde(skip)]
-    pub file_name: String,
-    pub version: String,
-    pub sha1: String,
-}
+    /// Finds maps across all benchmark sets that share a sha256 but not a name. Such
+    /// maps are equal, so one of them usually replaces the other when they end up in
+    /// the same set. This is a warning rather than part of `validate`, since the same
+    /// save may legitimately be named differently in different sets. Groups are sorted
+    /// by sha256.
+    pub fn find_duplicate_map_hashes(&self) -> Vec<DuplicateMapHash> {
+        let mut by_hash: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+        for (set_name, set) in &self.benchmark_sets {
+            for map in &set.maps {
+                by_hash
+                    .entry(map.sha256.to_lowercase())
+                    .or_default()
+                    .push((set_name.to_string(), map.name.to_string()));
+            }
+        }
+        by_hash
+            .into_iter()
+            .filter(|(_, maps)| maps.iter().any(|(_, name)| *name != maps[0].1))
+            .map(|(sha256, maps)| DuplicateMapHash { sha256, maps })
+            .collect()
+    }
 
-impl Mod {
-    pub fn new(name: &str, file_name: &str, version: &str, hash: &str) -> Mod {
-        Mod {
-            name: name.to_string(),
-            file_name: file_name.to_string(),
-            version: version.to_string(),
-            sha1: hash.to_string(),
+    /// Tidies up the inconsistencies that build up in a hand-edited file:
+    ///
+    /// - Map sha256 and mod sha1 hashes are lowercased.
+    /// - Leading and trailing whitespace is trimmed from map names, mod names, and the
+    ///   names of benchmark sets and meta sets. A set whose trimmed name is already
+    ///   taken keeps its name as it was.
+    /// - Meta set members are trimmed the same way, unless the member as written names
+    ///   a set, so references keep pointing where they did.
+    /// - Maps and mods that are equal after this, such as the same save with its hash
+    ///   written in two cases, are deduplicated within each set, keeping the first in
+    ///   the set's order.
+    pub fn normalize(&mut self) {
+        self.benchmark_sets = trim_keys(std::mem::take(&mut self.benchmark_sets));
+        self.meta_sets = trim_keys(std::mem::take(&mut self.meta_sets));
+        let names: HashSet<String> = self
+            .benchmark_sets
+            .keys()
+            .chain(self.meta_sets.keys())
+            .cloned()
+            .collect();
+        for members in self.meta_sets.values_mut() {
+            *members = members
+                .iter()
+                .map(|member| {
+                    if names.contains(member) {
+                        member.to_string()
+                    } else {
+                        member.trim().to_string()
+                    }
+                })
+                .collect();
+        }
+        for set in self.benchmark_sets.values_mut() {
+            let maps: Vec<Map> = std::mem::take(&mut set.maps)
+                .into_iter()
+                .map(|mut map| {
+                    map.name = map.name.trim().to_string();
+                    map.sha256 = map.sha256.to_lowercase();
+                    map
+                })
+                .collect();
+            set.maps = unique_items(maps.iter());
+            let mods: Vec<Mod> = std::mem::take(&mut set.mods)
+                .into_iter()
+                .map(|mut m| {
+                    m.name = m.name.trim().to_string();
+                    m.sha1 = m.sha1.to_lowercase();
+                    m
+                })
+                .collect();
+            set.mods = unique_items(mods.iter());
         }
     }
 }
 
-impl PartialEq for Mod {
-    fn eq(&self, cmp: &Self) -> bool {
-        if self.sha1 == cmp.sha1 && !self.sha1.is_empty() {
-            return true;
-        }
-        false
+/// Trims whitespace from every key whose trimmed form isn't already a key.
+fn trim_keys<V>(map: BTreeMap<String, V>) -> BTreeMap<String, V> {
+    let (mut clean, dirty): (BTreeMap<String, V>, BTreeMap<String, V>) =
+        map.into_iter().partition(|(key, _)| key.trim() == key);
+    for (key, value) in dirty {
+        let key = if clean.contains_key(key.trim()) {
+            key
+        } else {
+            key.trim().to_string()
+        };
+        clean.insert(key, value);
     }
+    clean
 }
 
+/// Maps found by `TopLevel::find_duplicate_map_hashes` that share a sha256, as
+/// `(set_name, map_name)` pairs.
 #[derive(Debug, PartialEq, Clone)]
-pub enum ProcedureError {
-    ProcedureAlreadyExists,
-    FileNotFound,
-    MalformedJSON,
-    UnknownReadError,
-    SetNotPresent,
+pub struct DuplicateMapHash {
+    pub sha256: String,
+    pub maps: Vec<(String, String)>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum ProcedureKind {
-    Benchmark,
-    Meta,
-    Both,
+fn is_valid_mod_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
 }
 
+fn is_hex_digest(s: &str, len: usize) -> bool {
+    s.len() == len && s.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
 
-/// When writing to a file, if there is already a procedure with the same name, should this procedure be overwritten?
+/// Tunable limits for `TopLevel::validate_with`.
 #[derive(Debug, PartialEq, Copy, Clone)]
-pub enum ProcedureOverwrite {
-    True,
-    False,
+pub struct ValidationOptions {
+    pub min_ticks: u32,
+    pub min_runs: u32,
 }
 
-impl From<bool> for ProcedureOverwrite {
-    fn from(b: bool) -> ProcedureOverwrite {
-        if b {
-            ProcedureOverwrite::True
-        } else {
-            ProcedureOverwrite::False
+impl Default for ValidationOptions {
+    fn default() -> ValidationOptions {
+        ValidationOptions {
+            min_ticks: 1,
+            min_runs: 1,
         }
     }
 }
 
-impl Not for ProcedureOverwrite {
-    type Output = ProcedureOverwrite;
-    fn not(self) -> Self::Output {
-        match self {
-            ProcedureOverwrite::True => ProcedureOverwrite::False,
-            ProcedureOverwrite::False => ProcedureOverwrite::True,
+/// A problem found in a benchmark set by `TopLevel::validate`. `problem` is a human
+/// readable description, such as `runs is 0 but must be at least 1`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ValidationError {
+    pub set_name: String,
+    pub item: ItemRef,
+    pub problem: String,
+}
+
+/// Which part of a benchmark set a `ValidationError` is about.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ItemRef {
+    /// A field of the set itself, such as `ticks`.
+    Set,
+    /// The map with this name.
+    Map(String),
+    /// The mod with this name.
+    Mod(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.item {
+            ItemRef::Set => write!(f, "set {:?}: {}", self.set_name, self.problem),
+            ItemRef::Map(name) => write!(f, "set {:?}: map {:?} {}", self.set_name, name, self.problem),
+            ItemRef::Mod(name) => write!(f, "set {:?}: mod {:?} {}", self.set_name, name, self.problem),
         }
     }
 }
 
-pub fn load_top_level(s: &str) -> Result<TopLevel, ProcedureError> {
-    if let Ok(json) = serde_json::from_str(s) {
-        Ok(json)
-    } else {
-        Err(ProcedureError::MalformedJSON)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct BenchmarkSet {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_portable_path",
+        deserialize_with = "deserialize_portable_path"
+    )]
+    pub save_subdirectory: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub mods: BTreeSet<Mod>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub maps: BTreeSet<Map>,
+    pub ticks: u32,
+    pub runs: u32,
+    /// The Factorio version the saves need, such as `1.1.87`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factorio_version: Option<String>,
+    /// Free-form labels for grouping sets without a meta set, such as `trains`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// A note on what the set exercises. Informational only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// When the set was first written, as an RFC 3339 timestamp in UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// When the set was last written, as an RFC 3339 timestamp in UTC. The write
+    /// functions set this to the current time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// Unknown keys in this set's entry, kept for the same reason as `TopLevel::extra`.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Paths are always written with forward slashes so that a file generated on Windows
+/// works elsewhere. Both separators are accepted when reading, which means a
+/// backslash can't be part of a directory name.
+fn serialize_portable_path<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serializer.serialize_some(&path.to_string_lossy().replace('\\', "/")),
+        None => serializer.serialize_none(),
     }
 }
 
-pub fn load_top_level_from_file(file: &Path) -> Result<TopLevel,ProcedureError> {
-    if file.exists() {
-        if let Ok(s) = &read_to_string(file) {
-            load_top_level(s)
-        } else {
-            Err(ProcedureError::UnknownReadError)
-        }
-    } else {
-        Err(ProcedureError::FileNotFound)
+fn deserialize_portable_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+    let path: Option<String> = Option::deserialize(deserializer)?;
+    Ok(path.map(|p| PathBuf::from(p.replace('\\', "/"))))
+}
+
+/// A one line summary for logs, such as `BenchmarkSet(ticks=1000, runs=5, maps=3, mods=12)`.
+/// Use `Debug` for every field.
+impl fmt::Display for BenchmarkSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BenchmarkSet(ticks={}, runs={}, maps={}, mods={})",
+            self.ticks,
+            self.runs,
+            self.maps.len(),
+            self.mods.len()
+        )
     }
 }
 
-impl FromStr for ProcedureKind {
-    type Err = String;
-    fn from_str(s: &str) -> Result<ProcedureKind, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "benchmark" => Ok(ProcedureKind::Benchmark),
-            "meta" => Ok(ProcedureKind::Meta),
-            "both" => Ok(ProcedureKind::Both),
-            _ => Err(String::from("Error: UnknownProcedureType")),
+impl BenchmarkSet {
+    pub fn builder() -> BenchmarkSetBuilder {
+        BenchmarkSetBuilder::default()
+    }
+
+    /// Returns the directory this set's saves live in, `save_subdirectory` joined onto
+    /// `base`, or `base` itself if there is no subdirectory.
+    pub fn resolved_save_dir(&self, base: &Path) -> PathBuf {
+        match &self.save_subdirectory {
+            Some(subdirectory) => base.join(subdirectory),
+            None => base.to_path_buf(),
         }
     }
-}
 
-/// Reads a benchmark set from a file, returning Err if the file doesn't exist or doesn't contain the supplied benchmark set
-pub fn read_benchmark_set_from_file(
-    name: &str,
-    file: &Path,
-) -> Result<BenchmarkSet,ProcedureError> {
-    let m = load_top_level_from_file(file)?;
-    if m.benchmark_sets.contains_key(name) {
-        Ok(m.benchmark_sets[name].clone())
-    } else {
-        Err(ProcedureError::SetNotPresent)
+    /// Compares the benchmark content of two sets: the maps, mods, ticks, runs and
+    /// Factorio version. Where the saves are stored (`save_subdirectory`) and how the
+    /// set is labelled (`tags`, `description`) and when it was written (`created`,
+    /// `modified`) are ignored. Two sets stored in different
+    /// subdirectories but running the same maps and mods the same way are
+    /// `content_eq` but not `==`, since the derived `PartialEq` compares every field.
+    pub fn content_eq(&self, other: &BenchmarkSet) -> bool {
+        self.mods == other.mods
+            && self.maps == other.maps
+            && self.ticks == other.ticks
+            && self.runs == other.runs
+            && self.factorio_version == other.factorio_version
     }
-}
 
-/// Writes a benchmark set to a file, if supplied file does not exist it will created
-pub fn write_benchmark_set_to_file(
-    set_name: &str,
-    set: BenchmarkSet,
-    overwrite: ProcedureOverwrite,
-    file: &Path,
-) -> Result<(),ProcedureError> {
-    let mut top_level;
-    match load_top_level_from_file(&file) {
-        Ok(m) => {
-            top_level = m;
+    /// Returns the maps of this set that `other` doesn't have, compared by sha256, such
+    /// as the maps to download when moving from `other` to this set.
+    pub fn maps_not_in(&self, other: &BenchmarkSet) -> BTreeSet<Map> {
+        items_not_in(&self.maps, &other.maps)
+    }
+
+    /// Returns the mods of this set that `other` doesn't have, compared by sha1, or by
+    /// name and version for mods without a sha1.
+    pub fn mods_not_in(&self, other: &BenchmarkSet) -> BTreeSet<Mod> {
+        items_not_in(&self.mods, &other.mods)
+    }
+
+    /// Merges another set into this one:
+    ///
+    /// - `maps` and `mods` become the union of both sets. A map or mod of `other` that
+    ///   is equal to one already here, as `add_map` and `add_mod` compare them, is
+    ///   skipped so this set's copy is kept.
+    /// - `ticks` and `runs` become the higher of the two values.
+    /// - `tags` become the union of both sets.
+    /// - `save_subdirectory`, `factorio_version`, `description`, `created` and
+    ///   `modified` keep this set's value, taking `other`'s only if this set has none.
+    /// - `extra` keeps this set's value for keys present in both.
+    pub fn merge_into(&mut self, other: &BenchmarkSet) {
+        for map in &other.maps {
+            self.add_map(map.clone());
+        }
+        for m in &other.mods {
+            self.add_mod(m.clone());
+        }
+        self.ticks = self.ticks.max(other.ticks);
+        self.runs = self.runs.max(other.runs);
+        self.tags.extend(other.tags.iter().cloned());
+        if self.save_subdirectory.is_none() {
+            self.save_subdirectory = other.save_subdirectory.clone();
+        }
+        if self.factorio_version.is_none() {
+            self.factorio_version = other.factorio_version.clone();
         }
-        _ => {
-            top_level = TopLevel::default();
+        if self.description.is_none() {
+            self.description = other.description.clone();
+        }
+        if self.created.is_none() {
+            self.created = other.created.clone();
+        }
+        if self.modified.is_none() {
+            self.modified = other.modified.clone();
+        }
+        for (key, value) in &other.extra {
+            self.extra.entry(key.to_string()).or_insert_with(|| value.clone());
         }
     }
-    if top_level.benchmark_sets.contains_key(set_name) && overwrite == false.into() {
-        return Err(ProcedureError::ProcedureAlreadyExists);
-    } else {
-        top_level.benchmark_sets.insert(set_name.to_string(), set);
-        let j = serde_json::to_string_pretty(&top_level).unwrap();
-        std::fs::write(file, j).unwrap();
+
+    /// Adds a map, returning false if an equal map (same sha256) was already present.
+    pub fn add_map(&mut self, map: Map) -> bool {
+        if self.maps.iter().any(|m| *m == map) {
+            return false;
+        }
+        self.maps.insert(map)
     }
-    Ok(())
-}
 
-/// Reads the immediate members of this meta set. No recursion to meta sets
-/// that are a child of this meta set.
-pub fn read_meta_from_file(name: &str, file: &Path) -> Result<BTreeSet<String>,ProcedureError> {
-    let m = load_top_level_from_file(&file)?;
-    if m.meta_sets.contains_key(name) {
-        Ok(m.meta_sets[name].clone())
-    } else {
-        Err(ProcedureError::SetNotPresent)
+    /// Removes every map with this sha256, compared case-insensitively, returning
+    /// true if any were removed.
+    pub fn remove_map_by_sha(&mut self, sha: &str) -> bool {
+        let before = self.maps.len();
+        self.maps.retain(|m| !hashes_match(&m.sha256, sha));
+        self.maps.len() != before
     }
-}
 
-/// Writes a meta set to file. If the file doesn't exist it will be created.
-pub fn write_meta_to_file(
-    name: &str,
-    members: BTreeSet<String>,
-    force: ProcedureOverwrite,
-    file: &Path,
-) -> Result<(),ProcedureError> {
-    let mut top_level;
-    match load_top_level_from_file(&file) {
-        Ok(m) => top_level = m,
-        _ => top_level = TopLevel::default(),
+    /// True if the set has a map with this sha256, compared case-insensitively.
+    pub fn contains_map_sha(&self, sha: &str) -> bool {
+        self.maps.iter().any(|m| hashes_match(&m.sha256, sha))
     }
 
-    if top_level.meta_sets.contains_key(name) && force == false.into() {
-        return Err(ProcedureError::ProcedureAlreadyExists);
-    } else {
-        top_level.meta_sets.insert(name.to_string(), members);
-        let j = serde_json::to_string_pretty(&top_level).unwrap();
-        std::fs::write(file, j).unwrap();
+    pub fn get_map_by_name(&self, name: &str) -> Option<&Map> {
+        self.maps.iter().find(|m| m.name == name)
     }
-    Ok(())
-}
 
-/// Returns a hashmap of all benchmark sets contained within this meta set, as well
-/// as any benchmark sets found recursively within any meta sets contained within
-/// this meta set.
-pub fn get_benchmarks_from_meta(
-    meta_set_key: &str,
-    file: &Path,
-) -> Result<HashMap<String, BenchmarkSet>,ProcedureError> {
-    let mut current_sets = HashMap::new();
-    let mut seen_keys = Vec::new();
-    let top_level = load_top_level_from_file(&file)?;
-    walk_meta_recursive_for_benchmarks(meta_set_key.to_string(), &top_level, &mut seen_keys, &mut current_sets);
-    Ok(current_sets)
-}
-
-fn walk_meta_recursive_for_benchmarks(
-    key: String,
-    top_level: &TopLevel,
-    seen_keys: &mut Vec<String>,
-    current_benchmark_sets: &mut HashMap<String, BenchmarkSet>,
-) {
-    if !seen_keys.contains(&key) {
-        if top_level.meta_sets.contains_key(&key) {
-            seen_keys.push(key.clone());
-            for k in &top_level.meta_sets[&key] {
-                walk_meta_recursive_for_benchmarks(
-                    k.to_string(),
-                    &top_level,
-                    seen_keys,
-                    current_benchmark_sets,
-                );
-            }
-        }
-        if top_level.benchmark_sets.contains_key(&key) {
-            current_benchmark_sets.insert(key.clone(), top_level.benchmark_sets[&key].to_owned());
+    /// Adds a mod, returning false if an equal mod was already present.
+    pub fn add_mod(&mut self, m: Mod) -> bool {
+        if self.mods.iter().any(|existing| *existing == m) {
+            return false;
         }
+        self.mods.insert(m)
+    }
+
+    /// True if the set has any version of the named mod.
+    pub fn contains_mod_name(&self, name: &str) -> bool {
+        self.mods.iter().any(|m| m.name == name)
+    }
+
+    /// Returns the named mod. If several versions are present, the newest is returned.
+    pub fn get_mod_by_name(&self, name: &str) -> Option<&Mod> {
+        self.mods.iter().rfind(|m| m.name == name)
+    }
+
+    /// Removes every version of the named mod, returning true if any were removed.
+    pub fn remove_mod_by_name(&mut self, name: &str) -> bool {
+        let before = self.mods.len();
+        self.mods.retain(|m| m.name != name);
+        self.mods.len() != before
     }
 }
 
-/// Returns all meta sets that are a part of this meta set, recursively
-pub fn get_metas_from_meta(
-    meta_set_key: &str,
-    file: &Path,
-) -> Result<BTreeSet<String>,ProcedureError> {
-    let mut seen_keys = BTreeSet::new();
-    let mut current_meta_sets = BTreeSet::new();
-    let top_level = load_top_level_from_file(&file)?;
-    walk_meta_recursive_for_metas(
-        meta_set_key.to_string(),
-        &top_level,
-        &mut seen_keys,
-        &mut current_meta_sets,
-    );
-    Ok(current_meta_sets)
+/// Builds a `BenchmarkSet` fluently. `build` does not check the result; use
+/// `TopLevel::validate` for that.
+#[derive(Debug, Clone, Default)]
+pub struct BenchmarkSetBuilder {
+    set: BenchmarkSet,
 }
 
-fn walk_meta_recursive_for_metas(
-    key: String,
-    top_level: &TopLevel,
-    seen_keys: &mut BTreeSet<String>,
-    current_meta_sets: &mut BTreeSet<String>,
-) {
-    if !seen_keys.contains(&key) && top_level.meta_sets.contains_key(&key) {
-        seen_keys.insert(key.clone());
-        for k in &top_level.meta_sets[&key] {
-            walk_meta_recursive_for_metas(k.to_string(), &top_level, seen_keys, current_meta_sets);
-        }
-        current_meta_sets.insert(key);
+impl BenchmarkSetBuilder {
+    pub fn ticks(mut self, ticks: u32) -> BenchmarkSetBuilder {
+        self.set.ticks = ticks;
+        self
+    }
+
+    pub fn runs(mut self, runs: u32) -> BenchmarkSetBuilder {
+        self.set.runs = runs;
+        self
+    }
+
+    pub fn save_subdirectory(mut self, save_subdirectory: &Path) -> BenchmarkSetBuilder {
+        self.set.save_subdirectory = Some(save_subdirectory.to_path_buf());
+        self
+    }
+
+    pub fn factorio_version(mut self, version: &str) -> BenchmarkSetBuilder {
+        self.set.factorio_version = Some(version.to_string());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> BenchmarkSetBuilder {
+        self.set.tags.insert(tag.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> BenchmarkSetBuilder {
+        self.set.description = Some(description.to_string());
+        self
+    }
+
+    pub fn add_map(mut self, map: Map) -> BenchmarkSetBuilder {
+        self.set.add_map(map);
+        self
+    }
+
+    pub fn add_mod(mut self, m: Mod) -> BenchmarkSetBuilder {
+        self.set.add_mod(m);
+        self
+    }
+
+    pub fn build(self) -> BenchmarkSet {
+        self.set
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Ord, Eq, PartialOrd)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Map {
+    pub name: String,
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub sha256: String,
+    pub download_link: String,
+    /// Fallback locations for the same save, tried in order if `download_link` fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// The algorithm that produced `sha256`. Despite the field's name it may hold a
+    /// sha512 digest for maps that are only distributed with one.
+    #[serde(default, skip_serializing_if = "HashAlgo::is_default")]
+    pub hash_algo: HashAlgo,
+    /// The size of the save in bytes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+}
+
+/// The checksum algorithm used for a map's hash.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgo {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    fn is_default(&self) -> bool {
+        *self == HashAlgo::default()
+    }
+
+    /// The length of this algorithm's digest as hex.
+    pub fn hex_len(&self) -> usize {
+        match self {
+            HashAlgo::Sha256 => 64,
+            HashAlgo::Sha512 => 128,
+        }
+    }
+
+    /// Streams a file and returns its digest as lowercase hex.
+    pub fn compute(&self, path: &Path) -> Result<String, io::Error> {
+        match self {
+            HashAlgo::Sha256 => compute_sha256(path),
+            HashAlgo::Sha512 => compute_sha512(path),
+        }
+    }
+
+    #[cfg(feature = "download")]
+    fn copy_and_hash(
+        &self,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<String, io::Error> {
+        match self {
+            HashAlgo::Sha256 => copy_and_hash::<Sha256>(reader, writer, progress),
+            HashAlgo::Sha512 => copy_and_hash::<Sha512>(reader, writer, progress),
+        }
+    }
+}
+
+impl Map {
+    pub fn new(path: &Path, sha256: &str, download_link: &str) -> Map {
+        Map {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            path: path.to_path_buf(),
+            sha256: sha256.to_string(),
+            download_link: download_link.to_string(),
+            mirrors: Vec::new(),
+            hash_algo: HashAlgo::Sha256,
+            size_bytes: None,
+        }
+    }
+
+    /// Returns where this map lives under `base`, usually a set's `resolved_save_dir`.
+    /// `path` is not stored in the file, so when it is empty the map's name is used.
+    /// An absolute `path` is returned unchanged.
+    pub fn resolved_path(&self, base: &Path) -> PathBuf {
+        if self.path.as_os_str().is_empty() {
+            base.join(&self.name)
+        } else {
+            base.join(&self.path)
+        }
+    }
+
+    /// Creates a map from a save on disk, computing its sha256 and recording its size.
+    pub fn from_path(path: &Path, download_link: &str) -> Result<Map, io::Error> {
+        let mut map = Map::new(path, &compute_sha256(path)?, download_link);
+        map.size_bytes = Some(std::fs::metadata(path)?.len());
+        Ok(map)
+    }
+
+    /// Hashes the file at `path` with `hash_algo` and checks it against the recorded
+    /// hash, ignoring case. The file is streamed, so large saves are never held in memory.
+    /// If `size_bytes` is recorded and the file is a different size, this returns false
+    /// without hashing.
+    pub fn verify(&self) -> Result<bool, io::Error> {
+        self.verify_file(&self.path)
+    }
+
+    /// Same as `verify`, but checks the file at `path` rather than `self.path`.
+    pub fn verify_file(&self, path: &Path) -> Result<bool, io::Error> {
+        if let Some(size) = self.size_bytes {
+            if std::fs::metadata(path)?.len() != size {
+                return Ok(false);
+            }
+        }
+        Ok(hashes_match(&self.hash_algo.compute(path)?, &self.sha256))
+    }
+
+    /// Downloads the map to `dest`, trying `download_link` and then each of `mirrors`
+    /// until one gives a file with the right hash. Each body is streamed to `dest`
+    /// with `.part` appended and only renamed into place once the hash is confirmed,
+    /// so `dest` never holds a bad download. If supplied, `progress` is called with
+    /// the bytes downloaded so far and the total size if the server sent one, roughly
+    /// every `DOWNLOAD_PROGRESS_INTERVAL` bytes and once more when a download completes.
+    /// If `size_bytes` is recorded and a server announces a different length, that
+    /// location is skipped without downloading the body.
+    /// When there is only one location its error is returned as is, otherwise the
+    /// error lists every location tried and why it failed.
+    #[cfg(feature = "download")]
+    pub fn download(
+        &self,
+        dest: &Path,
+        progress: Option<&mut dyn FnMut(u64, Option<u64>)>,
+    ) -> Result<(), DownloadError> {
+        let mut part = dest.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        let mut no_progress = |_, _| {};
+        let progress = progress.unwrap_or(&mut no_progress);
+        let mut failures = Vec::new();
+        let sources = std::iter::once(&self.download_link).chain(&self.mirrors);
+        for url in sources.filter(|url| !url.is_empty()) {
+            let result = self.download_to(url, &part, progress).and_then(|actual| {
+                if hashes_match(&actual, &self.sha256) {
+                    Ok(std::fs::rename(&part, dest)?)
+                } else {
+                    Err(DownloadError::HashMismatch {
+                        expected: self.sha256.to_string(),
+                        actual,
+                    })
+                }
+            });
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    let _ = std::fs::remove_file(&part);
+                    failures.push((url.to_string(), e));
+                }
+            }
+        }
+        if failures.len() == 1 {
+            Err(failures.remove(0).1)
+        } else {
+            Err(DownloadError::AllSourcesFailed(failures))
+        }
+    }
+
+    /// Streams the body at `url` into `path`, returning its hash.
+    #[cfg(feature = "download")]
+    fn download_to(
+        &self,
+        url: &str,
+        path: &Path,
+        progress: &mut dyn FnMut(u64, Option<u64>),
+    ) -> Result<String, DownloadError> {
+        // Saves can take far longer than reqwest's default 30 second timeout.
+        let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+        let mut response = client.get(url).send()?;
+        if !response.status().is_success() {
+            return Err(DownloadError::Status(response.status().as_u16()));
+        }
+        let total = response.content_length();
+        if let (Some(expected), Some(actual)) = (self.size_bytes, total) {
+            if expected != actual {
+                return Err(DownloadError::SizeMismatch { expected, actual });
+            }
+        }
+        let mut copied = 0;
+        let mut reported = 0;
+        let mut throttled = |so_far: u64| {
+            copied = so_far;
+            if so_far - reported >= DOWNLOAD_PROGRESS_INTERVAL {
+                reported = so_far;
+                progress(so_far, total);
+            }
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        let actual = self.hash_algo.copy_and_hash(&mut response, &mut writer, &mut throttled)?;
+        progress(copied, total);
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(actual)
+    }
+}
+
+/// Parses a Factorio mod zip file name such as `bobinserters_1.2.3.zip` into its
+/// name and version. The version is everything after the last underscore, and must
+/// be a dotted numeric version like `1.2.3`, so mod names that themselves contain
+/// underscores are handled. Returns None if the file name doesn't fit this pattern.
+pub fn parse_mod_filename(file_name: &str) -> Option<(String, String)> {
+    let stem = file_name.strip_suffix(".zip")?;
+    let (name, version) = stem.rsplit_once('_')?;
+    let is_version = version
+        .split('.')
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    if name.is_empty() || !is_version {
+        return None;
+    }
+    Some((name.to_string(), version.to_string()))
+}
+
+/// Streams a file and returns its sha1 as a 40 character lowercase hex string.
+pub fn compute_sha1(path: &Path) -> Result<String, io::Error> {
+    hash_file::<Sha1>(path)
+}
+
+/// Streams a file and returns its sha256 as a 64 character lowercase hex string.
+pub fn compute_sha256(path: &Path) -> Result<String, io::Error> {
+    hash_file::<Sha256>(path)
+}
+
+/// Streams a file and returns its sha512 as a 128 character lowercase hex string.
+pub fn compute_sha512(path: &Path) -> Result<String, io::Error> {
+    hash_file::<Sha512>(path)
+}
+
+/// Hex digests are compared case-insensitively everywhere, as tools differ in which
+/// case they print.
+fn hashes_match(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Streams a file through the supplied digest, returning the lowercase hex result.
+fn hash_file<D: Digest>(path: &Path) -> Result<String, io::Error> {
+    copy_and_hash::<D>(&mut File::open(path)?, &mut io::sink(), &mut |_| {})
+}
+
+/// Copies everything from the reader to the writer, hashing it on the way through,
+/// and returns the lowercase hex digest. `progress` is called with the number of
+/// bytes copied so far after every chunk.
+fn copy_and_hash<D: Digest>(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    progress: &mut dyn FnMut(u64),
+) -> Result<String, io::Error> {
+    let mut hasher = D::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut copied = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+        progress(copied);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Why downloading a map failed.
+#[cfg(feature = "download")]
+#[derive(Debug, PartialEq, Clone)]
+pub enum DownloadError {
+    /// The request could not be sent or the connection broke.
+    Request(String),
+    /// The server answered with a status other than success.
+    Status(u16),
+    Io(io::ErrorKind),
+    /// The downloaded file does not have the map's hash.
+    HashMismatch { expected: String, actual: String },
+    /// The server announced a body of a different size than the map's `size_bytes`,
+    /// so it was not downloaded.
+    SizeMismatch { expected: u64, actual: u64 },
+    /// Every location was tried, listed with the reason each failed. An empty list
+    /// means the map has no download locations at all.
+    AllSourcesFailed(Vec<(String, DownloadError)>),
+}
+
+#[cfg(feature = "download")]
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::Request(e) => write!(f, "the download request failed: {}", e),
+            DownloadError::Status(status) => write!(f, "the server responded with status {}", status),
+            DownloadError::Io(kind) => write!(f, "the download could not be saved: {:?}", kind),
+            DownloadError::HashMismatch { expected, actual } => {
+                write!(f, "the download has hash {} but {} was expected", actual, expected)
+            }
+            DownloadError::SizeMismatch { expected, actual } => {
+                write!(f, "the server offered {} bytes but {} were expected", actual, expected)
+            }
+            DownloadError::AllSourcesFailed(failures) => {
+                write!(f, "every download location failed")?;
+                for (url, e) in failures {
+                    write!(f, "; {}: {}", url, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "download")]
+impl std::error::Error for DownloadError {}
+
+#[cfg(feature = "download")]
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> DownloadError {
+        DownloadError::Io(e.kind())
+    }
+}
+
+#[cfg(feature = "download")]
+impl From<reqwest::Error> for DownloadError {
+    fn from(e: reqwest::Error) -> DownloadError {
+        DownloadError::Request(e.to_string())
+    }
+}
+
+impl PartialEq for Map {
+    fn eq(&self, cmp: &Self) -> bool {
+        if self.sha256 == cmp.sha256 {
+            return true;
+        }
+        false
+    }
+}
+
+/// Hashes only the sha256, to agree with `PartialEq`.
+impl Hash for Map {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sha256.hash(state);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Mod {
+    pub name: String,
+    #[serde(skip)]
+    pub file_name: String,
+    pub version: String,
+    pub sha1: String,
+    /// Whether Factorio should load the mod, as written to `mod-list.json`. Only
+    /// disabled mods record this in the file.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// The mod's dependencies as written in its `info.json`, such as
+    /// `? other_mod >= 1.2`; see `missing_dependencies`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(b: &bool) -> bool {
+    *b
+}
+
+impl Mod {
+    pub fn new(name: &str, file_name: &str, version: &str, hash: &str) -> Mod {
+        Mod {
+            name: name.to_string(),
+            file_name: file_name.to_string(),
+            version: version.to_string(),
+            sha1: hash.to_string(),
+            enabled: true,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// The file name Factorio expects for this mod's zip, `name_version.zip`.
+    pub fn expected_file_name(&self) -> String {
+        format!("{}_{}.zip", self.name, self.version)
+    }
+
+    /// Creates a mod from a zip on disk, computing its sha1. The name and version are
+    /// taken from the file name, which Factorio requires to be `name_version.zip`.
+    pub fn from_file(path: &Path) -> Result<Mod, io::Error> {
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match parse_mod_filename(&file_name) {
+            Some((name, version)) => Ok(Mod::new(&name, &file_name, &version, &compute_sha1(path)?)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not named like a mod zip (name_version.zip)", file_name),
+            )),
+        }
+    }
+
+    /// Hashes the mod zip at `path` and checks it against the recorded sha1, ignoring
+    /// case. A mod with no recorded sha1 cannot be verified and returns an error of
+    /// kind `InvalidInput` rather than passing.
+    pub fn verify(&self, path: &Path) -> Result<bool, io::Error> {
+        if self.sha1.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("mod {} has no sha1 to verify against", self.name),
+            ));
+        }
+        Ok(hashes_match(&compute_sha1(path)?, &self.sha1))
+    }
+}
+
+/// Mods are ordered by name, then by version compared numerically so that `1.10.0`
+/// sorts after `1.9.0`, then by the version string so that `1.02` and `1.2` stay
+/// distinct, then by sha1.
+impl Ord for Mod {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name
+            .cmp(&other.name)
+            .then_with(|| compare_versions(&self.version, &other.version))
+            .then_with(|| self.version.cmp(&other.version))
+            .then_with(|| self.sha1.cmp(&other.sha1))
+    }
+}
+
+impl PartialOrd for Mod {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares dotted numeric versions part by part. Versions that don't parse sort
+/// after every version that does and are compared as strings among themselves, which
+/// keeps the order total.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_version(a), parse_version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Mods are equal if they share a sha1. Mods with no recorded sha1 are instead
+/// equal if they share a name and version.
+impl PartialEq for Mod {
+    fn eq(&self, cmp: &Self) -> bool {
+        if self.sha1.is_empty() && cmp.sha1.is_empty() {
+            return self.name == cmp.name && self.version == cmp.version;
+        }
+        self.sha1 == cmp.sha1
+    }
+}
+
+/// Hashes the sha1, or the name and version when there is no sha1, to agree with
+/// `PartialEq`.
+impl Hash for Mod {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if self.sha1.is_empty() {
+            self.name.hash(state);
+            self.version.hash(state);
+        } else {
+            self.sha1.hash(state);
+        }
+    }
+}
+
+/// Renders the mods of a set as the `mod-list.json` Factorio reads to decide which
+/// mods to load, `{"mods": [{"name": ..., "enabled": ...}]}`. The file has no
+/// versions, so when a set holds several versions of a mod there is one entry, for the
+/// newest. `base` is listed first and enabled, unless the set has its own entry for it.
+pub fn to_mod_list_json(set: &BenchmarkSet) -> String {
+    let mut enabled = BTreeMap::new();
+    enabled.insert("base", true);
+    // Mods iterate oldest version first, so the newest version's flag wins.
+    for m in &set.mods {
+        enabled.insert(m.name.as_str(), m.enabled);
+    }
+    let mut mods = vec![serde_json::json!({"name": "base", "enabled": enabled["base"]})];
+    for (name, enabled) in enabled.into_iter().filter(|(name, _)| *name != "base") {
+        mods.push(serde_json::json!({"name": name, "enabled": enabled}));
+    }
+    format!("{:#}", serde_json::json!({ "mods": mods }))
+}
+
+/// Returns every required dependency of the set's enabled mods that no enabled mod in
+/// the set satisfies, as `mod: dependency`, such as `bobinserters: boblibrary >= 1.1.0`.
+/// Optional (`?`, `(?)`) and incompatible (`!`) dependencies are ignored, while `~`
+/// only affects load order and so is still required. `base` is provided by the game
+/// and always counts as present. A mod with no recorded version satisfies any
+/// version requirement, as there is nothing to check.
+pub fn missing_dependencies(set: &BenchmarkSet) -> Vec<String> {
+    let mut missing = Vec::new();
+    for m in set.mods.iter().filter(|m| m.enabled) {
+        for dependency in &m.dependencies {
+            let (kind, name, requirement) = parse_dependency(dependency);
+            if kind != DependencyKind::Required || name == "base" {
+                continue;
+            }
+            let satisfied = set
+                .mods
+                .iter()
+                .filter(|d| d.enabled && d.name == name)
+                .any(|d| match requirement {
+                    None => true,
+                    Some((op, version)) => d.version.is_empty() || version_satisfies(&d.version, op, version),
+                });
+            if !satisfied {
+                missing.push(format!("{}: {}", m.name, dependency.trim()));
+            }
+        }
+    }
+    missing
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum DependencyKind {
+    Required,
+    Optional,
+    Incompatible,
+}
+
+/// Splits a dependency like `? other_mod >= 1.2` into its kind, the mod name and the
+/// version requirement as `(operator, version)` if there is one.
+fn parse_dependency(dependency: &str) -> (DependencyKind, &str, Option<(&str, &str)>) {
+    let dependency = dependency.trim();
+    let (kind, rest) = if let Some(rest) = dependency.strip_prefix("(?)") {
+        (DependencyKind::Optional, rest)
+    } else if let Some(rest) = dependency.strip_prefix('?') {
+        (DependencyKind::Optional, rest)
+    } else if let Some(rest) = dependency.strip_prefix('!') {
+        (DependencyKind::Incompatible, rest)
+    } else if let Some(rest) = dependency.strip_prefix('~') {
+        (DependencyKind::Required, rest)
+    } else {
+        (DependencyKind::Required, dependency)
+    };
+    let rest = rest.trim();
+    match rest.find(['<', '>', '=']) {
+        Some(i) => {
+            let op_len = if rest[i + 1..].starts_with('=') { 2 } else { 1 };
+            let requirement = (&rest[i..i + op_len], rest[i + op_len..].trim());
+            (kind, rest[..i].trim(), Some(requirement))
+        }
+        None => (kind, rest, None),
+    }
+}
+
+fn version_satisfies(version: &str, op: &str, required: &str) -> bool {
+    let ordering = compare_versions(version, required);
+    match op {
+        "<" => ordering == Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        "=" => ordering == Ordering::Equal,
+        ">=" => ordering != Ordering::Less,
+        ">" => ordering == Ordering::Greater,
+        _ => false,
+    }
+}
+
+/// Reads Factorio's `mod-list.json` into mods, the inverse of `to_mod_list_json`. The
+/// file only names mods, so the sha1 is left empty and so is the version, unless the
+/// entry pins one. The `base` entry is skipped, since the game itself provides it.
+pub fn mods_from_mod_list_json(s: &str) -> Result<BTreeSet<Mod>,ProcedureError> {
+    #[derive(Deserialize)]
+    struct ModList {
+        mods: Vec<ModListEntry>,
+    }
+    #[derive(Deserialize)]
+    struct ModListEntry {
+        name: String,
+        #[serde(default = "default_true")]
+        enabled: bool,
+        #[serde(default)]
+        version: String,
+    }
+    let list: ModList = serde_json::from_str(s)?;
+    Ok(list
+        .mods
+        .into_iter()
+        .filter(|entry| entry.name != "base")
+        .map(|entry| Mod {
+            enabled: entry.enabled,
+            ..Mod::new(&entry.name, "", &entry.version, "")
+        })
+        .collect())
+}
+
+/// Builds mods from every zip in a Factorio mods directory, taking the name and version
+/// from each file name and hashing the zip for its sha1. Anything that isn't a file
+/// named like `name_version.zip` is skipped, which covers `mod-list.json`,
+/// `mod-settings.dat` and mods unpacked into folders.
+pub fn scan_mods_dir(dir: &Path) -> Result<BTreeSet<Mod>, io::Error> {
+    let mut mods = BTreeSet::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type()?.is_file() || parse_mod_filename(&file_name).is_none() {
+            debug!("skipped {} while scanning mods", file_name);
+            continue;
+        }
+        mods.insert(Mod::from_file(&entry.path())?);
+    }
+    Ok(mods)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProcedureError {
+    ProcedureAlreadyExists,
+    /// Several procedures already exist, listed by name.
+    ProceduresAlreadyExist(Vec<String>),
+    FileNotFound,
+    MalformedJSON(String),
+    MalformedTOML(String),
+    MalformedYAML(String),
+    /// An IO error other than the file not existing, such as permission denied.
+    Io(io::ErrorKind),
+    SetNotPresent,
+    /// The serializer's reason, such as TOML having no way to write a `null`.
+    SerializationFailed(String),
+    WriteFailed(io::ErrorKind),
+    /// The meta sets contain a cycle, given as the path of names back to its start.
+    MetaCycle(Vec<String>),
+    /// A strict load found a key it doesn't know, given as its path in the file, such
+    /// as `benchmark_sets.example.tick`.
+    UnknownField(String),
+    /// These maps have no recorded `size_bytes`, listed by name.
+    MissingMapSizes(Vec<String>),
+}
+
+impl fmt::Display for ProcedureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcedureError::ProcedureAlreadyExists => write!(f, "a procedure with this name already exists"),
+            ProcedureError::ProceduresAlreadyExist(names) => {
+                write!(f, "procedures with these names already exist: {}", names.join(", "))
+            }
+            ProcedureError::FileNotFound => write!(f, "the procedure file was not found"),
+            ProcedureError::MalformedJSON(e) => write!(f, "the procedure file is not valid JSON: {}", e),
+            ProcedureError::MalformedTOML(e) => write!(f, "the procedure file is not valid TOML: {}", e),
+            ProcedureError::MalformedYAML(e) => write!(f, "the procedure file is not valid YAML: {}", e),
+            ProcedureError::Io(kind) => write!(f, "the procedure file could not be read: {:?}", kind),
+            ProcedureError::SetNotPresent => write!(f, "the requested set is not present in the procedure file"),
+            ProcedureError::SerializationFailed(e) => write!(f, "the procedures could not be serialized: {}", e),
+            ProcedureError::WriteFailed(kind) => write!(f, "the procedure file could not be written: {:?}", kind),
+            ProcedureError::MetaCycle(cycle) => write!(f, "the meta sets form a cycle: {}", cycle.join(" -> ")),
+            ProcedureError::UnknownField(field) => write!(f, "the procedure file has an unknown field: {}", field),
+            ProcedureError::MissingMapSizes(names) => {
+                write!(f, "these maps have no recorded size: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProcedureError {}
+
+impl From<io::Error> for ProcedureError {
+    fn from(e: io::Error) -> ProcedureError {
+        match e.kind() {
+            io::ErrorKind::NotFound => ProcedureError::FileNotFound,
+            kind => ProcedureError::Io(kind),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProcedureError {
+    fn from(e: serde_json::Error) -> ProcedureError {
+        match e.io_error_kind() {
+            Some(kind) => ProcedureError::Io(kind),
+            None => ProcedureError::MalformedJSON(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProcedureKind {
+    Benchmark,
+    Meta,
+    Both,
+}
+
+
+/// When writing to a file, if there is already a procedure with the same name, should this procedure be overwritten?
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ProcedureOverwrite {
+    True,
+    False,
+}
+
+impl From<bool> for ProcedureOverwrite {
+    fn from(b: bool) -> ProcedureOverwrite {
+        if b {
+            ProcedureOverwrite::True
+        } else {
+            ProcedureOverwrite::False
+        }
+    }
+}
+
+impl Not for ProcedureOverwrite {
+    type Output = ProcedureOverwrite;
+    fn not(self) -> Self::Output {
+        match self {
+            ProcedureOverwrite::True => ProcedureOverwrite::False,
+            ProcedureOverwrite::False => ProcedureOverwrite::True,
+        }
+    }
+}
+
+pub fn load_top_level(s: &str) -> Result<TopLevel, ProcedureError> {
+    Ok(finish_load(serde_json::from_str(s)?))
+}
+
+/// Brings a freshly parsed top level up to date, whatever format it was read from.
+fn finish_load(mut top_level: TopLevel) -> TopLevel {
+    fill_mod_file_names(&mut top_level);
+    migrate(top_level)
+}
+
+/// `Mod::file_name` is not stored in the file, so rebuild it from the name and version.
+fn fill_mod_file_names(top_level: &mut TopLevel) {
+    for set in top_level.benchmark_sets.values_mut() {
+        set.mods = std::mem::take(&mut set.mods)
+            .into_iter()
+            .map(|mut m| {
+                if m.file_name.is_empty() {
+                    m.file_name = m.expected_file_name();
+                }
+                m
+            })
+            .collect();
+    }
+}
+
+/// Parses the top level from a JSON string. Equivalent to `load_top_level`.
+pub fn from_json_str(s: &str) -> Result<TopLevel, ProcedureError> {
+    load_top_level(s)
+}
+
+/// Serializes the top level to a pretty printed JSON string, as written to files.
+pub fn to_json_string(top_level: &TopLevel) -> Result<String, ProcedureError> {
+    serde_json::to_string_pretty(top_level).map_err(|e| ProcedureError::SerializationFailed(e.to_string()))
+}
+
+/// Loads the top level from any reader, such as stdin or an HTTP response body.
+pub fn load_top_level_from_reader<R: Read>(reader: R) -> Result<TopLevel, ProcedureError> {
+    Ok(finish_load(serde_json::from_reader(reader)?))
+}
+
+/// The storage format of a procedure file, picked by its extension.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum FileFormat {
+    Json,
+    #[cfg(feature = "gzip")]
+    GzipJson,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl FileFormat {
+    /// Anything that isn't recognised, including no extension at all, is JSON.
+    fn from_path(path: &Path) -> FileFormat {
+        #[cfg(feature = "gzip")]
+        {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase());
+            if name.is_some_and(|n| n.ends_with(".json.gz")) {
+                return FileFormat::GzipJson;
+            }
+        }
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            #[cfg(feature = "toml")]
+            Some("toml") => FileFormat::Toml,
+            #[cfg(feature = "yaml")]
+            Some("yaml") | Some("yml") => FileFormat::Yaml,
+            _ => FileFormat::Json,
+        }
+    }
+}
+
+/// Loads the top level from a file for a writer to modify, starting out empty if the
+/// file doesn't exist yet. Any other error is returned, so a file that fails to parse
+/// is never overwritten with only the new changes.
+fn load_top_level_or_default(file: &Path) -> Result<TopLevel,ProcedureError> {
+    match load_top_level_from_file(file) {
+        Err(ProcedureError::FileNotFound) => Ok(TopLevel::default()),
+        result => result,
+    }
+}
+
+/// Loads the top level from a file. With the `toml` feature enabled, files ending in
+/// `.toml` are parsed as TOML, and with the `yaml` feature files ending in `.yaml` or
+/// `.yml` are parsed as YAML. With the `gzip` feature, files ending in `.json.gz` are
+/// decompressed and parsed as JSON. Everything else is parsed as JSON.
+pub fn load_top_level_from_file(file: &Path) -> Result<TopLevel,ProcedureError> {
+    match FileFormat::from_path(file) {
+        FileFormat::Json => load_top_level_from_reader(BufReader::new(File::open(file)?)),
+        #[cfg(feature = "gzip")]
+        FileFormat::GzipJson => {
+            load_top_level_from_reader(BufReader::new(flate2::read::GzDecoder::new(File::open(file)?)))
+        }
+        #[cfg(feature = "toml")]
+        FileFormat::Toml => {
+            let s = std::fs::read_to_string(file)?;
+            let top_level = toml::from_str(&s).map_err(|e| ProcedureError::MalformedTOML(e.to_string()))?;
+            Ok(finish_load(top_level))
+        }
+        #[cfg(feature = "yaml")]
+        FileFormat::Yaml => {
+            let reader = BufReader::new(File::open(file)?);
+            let top_level =
+                serde_yaml::from_reader(reader).map_err(|e| ProcedureError::MalformedYAML(e.to_string()))?;
+            Ok(finish_load(top_level))
+        }
+    }
+}
+
+/// Loads a file like `load_top_level_from_file`, but fails on the first key that is
+/// not part of the format instead of keeping it in `extra`, to catch typos such as
+/// `tick` for `ticks`. Only the top level and the benchmark set entries are checked;
+/// unknown keys inside maps and mods are still ignored, though a misspelt required
+/// key there fails the load anyway.
+pub fn load_top_level_strict(file: &Path) -> Result<TopLevel,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    if let Some(key) = top_level.extra.keys().next() {
+        return Err(ProcedureError::UnknownField(key.to_string()));
+    }
+    for (name, set) in &top_level.benchmark_sets {
+        if let Some(key) = set.extra.keys().next() {
+            return Err(ProcedureError::UnknownField(format!("benchmark_sets.{}.{}", name, key)));
+        }
+    }
+    Ok(top_level)
+}
+
+/// A file's parsed `TopLevel`, kept between calls and only reparsed once the file's
+/// modification time changes. Meant for tools that query the same file repeatedly;
+/// for one-off reads the plain functions are simpler. A change that keeps the mtime
+/// the same, as can happen for two writes within the filesystem's timestamp
+/// resolution, is not noticed.
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    path: PathBuf,
+    cached: Option<(std::time::SystemTime, TopLevel)>,
+}
+
+impl CachedFile {
+    /// Nothing is read until the first `get`.
+    pub fn new(path: &Path) -> CachedFile {
+        CachedFile {
+            path: path.to_path_buf(),
+            cached: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the parsed file, reloading it first if it changed on disk since the
+    /// last call. If reloading fails the stale copy is dropped.
+    pub fn get(&mut self) -> Result<&TopLevel,ProcedureError> {
+        // The mtime is read before the contents, so a write landing in between makes
+        // the next call reload again rather than the cache missing the change.
+        let modified = std::fs::metadata(&self.path)?.modified()?;
+        let top_level = match self.cached.take() {
+            Some((seen, top_level)) if seen == modified => top_level,
+            _ => load_top_level_from_file(&self.path)?,
+        };
+        Ok(&self.cached.insert((modified, top_level)).1)
+    }
+}
+
+/// A batch of edits to one file that is written all at once by `commit`, or not at all
+/// if the transaction is dropped. The file is loaded by `open`, or starts out empty if
+/// it doesn't exist yet, and the edits happen in memory. With the `locking` feature
+/// the file stays locked from `open` until the transaction is committed or dropped,
+/// so no other writer can change it in between. That includes this process: calling
+/// any of the `write_*`, `delete_*` or other editing functions on the same file, or
+/// opening a second transaction on it, while a transaction is open blocks forever.
+/// Make those edits through the transaction instead.
+#[derive(Debug)]
+pub struct Transaction {
+    path: PathBuf,
+    top_level: TopLevel,
+    _lock: WriteLock,
+}
+
+impl Transaction {
+    pub fn open(file: &Path) -> Result<Transaction,ProcedureError> {
+        let lock = lock_for_write(file)?;
+        let top_level = load_top_level_or_default(file)?;
+        Ok(Transaction {
+            path: file.to_path_buf(),
+            top_level,
+            _lock: lock,
+        })
+    }
+
+    pub fn top_level(&self) -> &TopLevel {
+        &self.top_level
+    }
+
+    /// For edits without a dedicated method.
+    pub fn top_level_mut(&mut self) -> &mut TopLevel {
+        &mut self.top_level
+    }
+
+    pub fn insert_benchmark_set(
+        &mut self,
+        name: &str,
+        set: BenchmarkSet,
+        overwrite: ProcedureOverwrite,
+    ) -> Result<Option<BenchmarkSet>,ProcedureError> {
+        self.top_level.insert_benchmark_set(name, set, overwrite)
+    }
+
+    pub fn insert_meta(
+        &mut self,
+        name: &str,
+        members: BTreeSet<String>,
+        overwrite: ProcedureOverwrite,
+    ) -> Result<Option<BTreeSet<String>>,ProcedureError> {
+        self.top_level.insert_meta(name, members, overwrite)
+    }
+
+    pub fn remove_benchmark_set(&mut self, name: &str) -> Option<BenchmarkSet> {
+        self.top_level.remove_benchmark_set(name)
+    }
+
+    pub fn remove_meta(&mut self, name: &str) -> Option<BTreeSet<String>> {
+        self.top_level.remove_meta(name)
+    }
+
+    /// Writes every edit to the file atomically. If this fails the file is left as it
+    /// was.
+    pub fn commit(self) -> Result<(),ProcedureError> {
+        write_top_level_to_file(&self.top_level, &self.path)
+    }
+}
+
+impl FromStr for ProcedureOverwrite {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ProcedureOverwrite, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "true" | "yes" | "overwrite" => Ok(ProcedureOverwrite::True),
+            "false" | "no" | "keep" => Ok(ProcedureOverwrite::False),
+            _ => Err(String::from("Error: UnknownOverwriteMode")),
+        }
+    }
+}
+
+impl FromStr for ProcedureKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ProcedureKind, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "benchmark" => Ok(ProcedureKind::Benchmark),
+            "meta" => Ok(ProcedureKind::Meta),
+            "both" | "all" => Ok(ProcedureKind::Both),
+            _ => Err(String::from("Error: UnknownProcedureType")),
+        }
+    }
+}
+
+impl fmt::Display for ProcedureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcedureKind::Benchmark => write!(f, "benchmark"),
+            ProcedureKind::Meta => write!(f, "meta"),
+            ProcedureKind::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// Returns the names of every benchmark set in a file, in sorted order.
+pub fn list_benchmark_set_names(file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level.benchmark_sets.keys().cloned().collect())
+}
+
+/// Returns the names of every meta set in a file, in sorted order.
+pub fn list_meta_set_names(file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level.meta_sets.keys().cloned().collect())
+}
+
+/// Returns the names of the benchmark sets containing `query`, ignoring case, in
+/// sorted order.
+pub fn search_benchmark_sets(query: &str, file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(names_containing(top_level.benchmark_sets.keys(), query))
+}
+
+/// Returns the names of the meta sets containing `query`, ignoring case, in sorted
+/// order.
+pub fn search_meta_sets(query: &str, file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(names_containing(top_level.meta_sets.keys(), query))
+}
+
+/// Returns the names of the benchmark sets matching a glob pattern, in sorted order.
+///
+/// The dialect is deliberately small: `*` matches any run of characters, including
+/// none, `?` matches exactly one character, and every other character matches only
+/// itself, case sensitively. There are no character classes, braces or escapes, and
+/// the pattern must match the whole name, so `train_*` matches `train_loop` but not
+/// `big_train_loop`.
+pub fn match_benchmark_sets(pattern: &str, file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level
+        .benchmark_sets
+        .keys()
+        .filter(|name| glob_matches(pattern, name))
+        .cloned()
+        .collect())
+}
+
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` seen, and where in the name it started matching.
+    // On a mismatch the star swallows one more character and matching resumes.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn names_containing<'a>(names: impl Iterator<Item = &'a String>, query: &str) -> Vec<String> {
+    let query = query.to_lowercase();
+    names
+        .filter(|name| name.to_lowercase().contains(&query))
+        .cloned()
+        .collect()
+}
+
+/// Reads a benchmark set from a file, returning Err if the file doesn't exist or doesn't contain the supplied benchmark set
+pub fn read_benchmark_set_from_file(
+    name: &str,
+    file: &Path,
+) -> Result<BenchmarkSet,ProcedureError> {
+    let m = load_top_level_from_file(file)?;
+    if m.benchmark_sets.contains_key(name) {
+        Ok(m.benchmark_sets[name].clone())
+    } else {
+        Err(ProcedureError::SetNotPresent)
+    }
+}
+
+/// Writes a benchmark set to a file, if supplied file does not exist it will created.
+/// Returns the set that was replaced, if overwrite was true and there was one.
+pub fn write_benchmark_set_to_file(
+    set_name: &str,
+    mut set: BenchmarkSet,
+    overwrite: ProcedureOverwrite,
+    file: &Path,
+) -> Result<Option<BenchmarkSet>,ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_or_default(file)?;
+    stamp_write(&mut set, top_level.benchmark_sets.get(set_name));
+    let replaced = top_level
+        .insert_benchmark_set(set_name, set, overwrite)
+        .inspect_err(|_| debug!("skipped benchmark set {:?}: already exists in {}", set_name, file.display()))?;
+    write_top_level_to_file(&top_level, file)?;
+    log_write("benchmark set", set_name, replaced.is_some(), file);
+    Ok(replaced)
+}
+
+fn log_write(kind: &str, name: &str, replaced: bool, file: &Path) {
+    let action = if replaced { "replaced" } else { "inserted" };
+    info!("{} {} {:?} in {}", action, kind, name, file.display());
+}
+
+/// Sets `modified` to now. `created` is kept if the set has one, and otherwise taken
+/// from the set being replaced, or set to now for a new set.
+fn stamp_write(set: &mut BenchmarkSet, previous: Option<&BenchmarkSet>) {
+    let now = now_rfc3339();
+    if set.created.is_none() {
+        set.created = previous.and_then(|p| p.created.clone()).or_else(|| Some(now.clone()));
+    }
+    set.modified = Some(now);
+}
+
+fn now_rfc3339() -> String {
+    humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string()
+}
+
+/// How many backups `write_benchmark_set_with_backup` keeps of a file.
+pub const MAX_BACKUPS: usize = 5;
+
+/// Writes a benchmark set like `write_benchmark_set_to_file`, but first copies the
+/// existing file, if any, to `<file>.<unix milliseconds>.<sequence>.bak` next to it.
+/// Only the newest `MAX_BACKUPS` backups are kept; older ones are deleted. Nothing is
+/// backed up or written if the set can't be inserted.
+pub fn write_benchmark_set_with_backup(
+    set_name: &str,
+    mut set: BenchmarkSet,
+    overwrite: ProcedureOverwrite,
+    file: &Path,
+) -> Result<Option<BenchmarkSet>,ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_or_default(file)?;
+    stamp_write(&mut set, top_level.benchmark_sets.get(set_name));
+    let replaced = top_level
+        .insert_benchmark_set(set_name, set, overwrite)
+        .inspect_err(|_| debug!("skipped benchmark set {:?}: already exists in {}", set_name, file.display()))?;
+    if file.exists() {
+        back_up_file(file).map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+        debug!("backed up {}", file.display());
+    }
+    write_top_level_to_file(&top_level, file)?;
+    log_write("benchmark set", set_name, replaced.is_some(), file);
+    Ok(replaced)
+}
+
+/// Copies rather than renames, so the file is never missing if the write that follows
+/// fails.
+fn back_up_file(file: &Path) -> io::Result<()> {
+    let file_name = match file.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(io::ErrorKind::InvalidInput.into()),
+    };
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    // Zero padded so that sorting the names sorts the backups by age. The sequence
+    // number keeps two backups taken in the same millisecond apart: the name is
+    // claimed with `create_new` before copying over it.
+    let mut sequence = 0;
+    let backup = loop {
+        let backup = file.with_file_name(format!("{}.{:013}.{:03}.bak", file_name, millis, sequence));
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&backup) {
+            Ok(_) => break backup,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => sequence += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    std::fs::copy(file, backup)?;
+
+    let dir = match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", file_name);
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let is_backup = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".bak"))
+            .is_some_and(|stamp| !stamp.is_empty() && stamp.chars().all(|c| c.is_ascii_digit() || c == '.'));
+        if is_backup {
+            backups.push(name);
+        }
+    }
+    backups.sort();
+    let excess = backups.len().saturating_sub(MAX_BACKUPS);
+    for name in &backups[..excess] {
+        std::fs::remove_file(dir.join(name))?;
+        debug!("removed old backup {}", name);
+    }
+    Ok(())
+}
+
+/// Performs the same steps as `write_benchmark_set_to_file`, including the overwrite
+/// check, but returns the JSON that would be written instead of touching the file.
+pub fn write_benchmark_set_dry_run(
+    set_name: &str,
+    mut set: BenchmarkSet,
+    overwrite: ProcedureOverwrite,
+    file: &Path,
+) -> Result<String,ProcedureError> {
+    let mut top_level = load_top_level_or_default(file)?;
+    stamp_write(&mut set, top_level.benchmark_sets.get(set_name));
+    top_level.insert_benchmark_set(set_name, set, overwrite)?;
+    to_json_string(&top_level)
+}
+
+/// Writes several benchmark sets to a file in a single load and write. If overwrite is
+/// false and any of the names are already taken, nothing is written and the error
+/// lists every colliding name.
+pub fn write_sets_to_file(
+    mut sets: BTreeMap<String, BenchmarkSet>,
+    overwrite: ProcedureOverwrite,
+    file: &Path,
+) -> Result<(),ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_or_default(file)?;
+    if overwrite == false.into() {
+        let collisions: Vec<String> = sets
+            .keys()
+            .filter(|name| top_level.benchmark_sets.contains_key(*name))
+            .cloned()
+            .collect();
+        if !collisions.is_empty() {
+            debug!("skipped writing to {}: {:?} already exist", file.display(), collisions);
+            return Err(ProcedureError::ProceduresAlreadyExist(collisions));
+        }
+    }
+    let mut replaced = Vec::new();
+    for (name, set) in sets.iter_mut() {
+        let previous = top_level.benchmark_sets.get(name);
+        replaced.push((name.to_string(), previous.is_some()));
+        stamp_write(set, previous);
+    }
+    top_level.benchmark_sets.extend(sets);
+    write_top_level_to_file(&top_level, file)?;
+    for (name, replaced) in replaced {
+        log_write("benchmark set", &name, replaced, file);
+    }
+    Ok(())
+}
+
+/// Removes a benchmark set from a file, returning true if the set was present.
+/// Meta sets are left untouched, so any meta set that listed this benchmark set
+/// will now hold a dangling reference; see `get_metas_referencing`.
+pub fn delete_benchmark_set_from_file(name: &str, file: &Path) -> Result<bool,ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    if top_level.remove_benchmark_set(name).is_some() {
+        write_top_level_to_file(&top_level, file)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Renames a benchmark set, rewriting every meta set member that referred to the
+/// old name. If a meta set shares the old name, those members are kept alongside the
+/// new name, since they still resolve to that meta set. Returns Err if the old set is
+/// not present or the new name is taken.
+pub fn rename_benchmark_set(old: &str, new: &str, file: &Path) -> Result<(),ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    if top_level.benchmark_sets.contains_key(new) {
+        return Err(ProcedureError::ProcedureAlreadyExists);
+    }
+    let set = match top_level.benchmark_sets.remove(old) {
+        Some(set) => set,
+        None => return Err(ProcedureError::SetNotPresent),
+    };
+    top_level.benchmark_sets.insert(new.to_string(), set);
+    let old_is_meta = top_level.meta_sets.contains_key(old);
+    for members in top_level.meta_sets.values_mut() {
+        if members.contains(old) {
+            if !old_is_meta {
+                members.remove(old);
+            }
+            members.insert(new.to_string());
+        }
+    }
+    write_top_level_to_file(&top_level, file)
+}
+
+/// Copies a benchmark set under a new name, for making a variant of an existing set.
+/// Meta sets are not touched, so the copy is not a member of any. Returns Err if the
+/// source set is not present or the new name is taken.
+pub fn copy_benchmark_set(src: &str, dst: &str, file: &Path) -> Result<(),ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    if top_level.benchmark_sets.contains_key(dst) {
+        return Err(ProcedureError::ProcedureAlreadyExists);
+    }
+    let set = match top_level.benchmark_sets.get(src) {
+        Some(set) => set.clone(),
+        None => return Err(ProcedureError::SetNotPresent),
+    };
+    top_level.benchmark_sets.insert(dst.to_string(), set);
+    write_top_level_to_file(&top_level, file)
+}
+
+/// Returns the meta sets that directly list the supplied name as a member.
+pub fn get_metas_referencing(name: &str, file: &Path) -> Result<BTreeSet<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level
+        .meta_sets
+        .iter()
+        .filter(|(_, members)| members.contains(name))
+        .map(|(meta, _)| meta.to_string())
+        .collect())
+}
+
+/// Reads the immediate members of this meta set. No recursion to meta sets
+/// that are a child of this meta set.
+pub fn read_meta_from_file(name: &str, file: &Path) -> Result<BTreeSet<String>,ProcedureError> {
+    let m = load_top_level_from_file(file)?;
+    if m.meta_sets.contains_key(name) {
+        Ok(m.meta_sets[name].clone())
+    } else {
+        Err(ProcedureError::SetNotPresent)
+    }
+}
+
+/// Returns the immediate members of a meta set, one level of a tree view at a time.
+/// This is the same as `read_meta_from_file`.
+pub fn direct_members(meta_key: &str, file: &Path) -> Result<BTreeSet<String>,ProcedureError> {
+    read_meta_from_file(meta_key, file)
+}
+
+/// Returns the immediate members of a meta set along with what each one is, as given
+/// by `TopLevel::classify`.
+pub fn classify_direct_members(
+    meta_key: &str,
+    file: &Path,
+) -> Result<BTreeMap<String, NameKind>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let members = match top_level.meta_sets.get(meta_key) {
+        Some(members) => members,
+        None => return Err(ProcedureError::SetNotPresent),
+    };
+    Ok(members
+        .iter()
+        .map(|member| (member.to_string(), top_level.classify(member)))
+        .collect())
+}
+
+/// Writes a meta set to file. If the file doesn't exist it will be created.
+pub fn write_meta_to_file(
+    name: &str,
+    members: BTreeSet<String>,
+    force: ProcedureOverwrite,
+    file: &Path,
+) -> Result<(),ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_or_default(file)?;
+    let replaced = top_level
+        .insert_meta(name, members, force)
+        .inspect_err(|_| debug!("skipped meta set {:?}: already exists in {}", name, file.display()))?;
+    write_top_level_to_file(&top_level, file)?;
+    log_write("meta set", name, replaced.is_some(), file);
+    Ok(())
+}
+
+/// Loads a file, tidies it with `TopLevel::normalize` and writes it back.
+pub fn normalize_file(file: &Path) -> Result<(),ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    top_level.normalize();
+    write_top_level_to_file(&top_level, file)
+}
+
+/// Removes a meta set from a file, returning true if the meta set was present.
+/// The name is also scrubbed from the members of every other meta set that
+/// listed it directly. Only that one level of references is touched; meta sets
+/// which reached it through another meta set are fixed by that removal, as the
+/// recursion happens at read time. If a benchmark set shares the name, references
+/// are kept since they still resolve to that benchmark set.
+pub fn delete_meta_from_file(name: &str, file: &Path) -> Result<bool,ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    if top_level.remove_meta(name).is_none() {
+        return Ok(false);
+    }
+    write_top_level_to_file(&top_level, file)?;
+    Ok(true)
+}
+
+/// An exclusive advisory lock held for the whole load-modify-write of a file, so two
+/// processes editing the same file can't lose each other's changes. It is released
+/// when dropped. Without the `locking` feature it holds nothing.
+#[derive(Debug)]
+struct WriteLock {
+    #[cfg(feature = "locking")]
+    _file: File,
+}
+
+/// Locks a `<file>.lock` sidecar next to the file rather than the file itself, since
+/// each write renames a new file over the target and a lock on the old one would be
+/// lost. The sidecar is left in place afterwards. The lock is advisory, so it only
+/// guards against other users of this crate.
+#[cfg(feature = "locking")]
+fn lock_for_write(file: &Path) -> Result<WriteLock,ProcedureError> {
+    use fs2::FileExt;
+    let mut lock_name = file.as_os_str().to_os_string();
+    lock_name.push(".lock");
+    let f = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_name)
+        .map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+    f.lock_exclusive().map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+    Ok(WriteLock { _file: f })
+}
+
+#[cfg(not(feature = "locking"))]
+fn lock_for_write(_file: &Path) -> Result<WriteLock,ProcedureError> {
+    Ok(WriteLock {})
+}
+
+/// Serializes the top level as pretty printed JSON into any writer, such as stdout
+/// or an in-memory buffer.
+pub fn write_top_level_to_writer<W: Write>(top_level: &TopLevel, mut writer: W) -> Result<(),ProcedureError> {
+    serde_json::to_writer_pretty(&mut writer, top_level).map_err(|e| match e.io_error_kind() {
+        Some(kind) => ProcedureError::WriteFailed(kind),
+        None => ProcedureError::SerializationFailed(e.to_string()),
+    })?;
+    writer.flush().map_err(|e| ProcedureError::WriteFailed(e.kind()))
+}
+
+/// Serializes the top level and writes it to file, replacing any existing contents.
+/// The write is atomic: the contents go to a temporary file in the same directory
+/// which is synced and then renamed over the target, so an interrupted write never
+/// leaves a truncated file behind. The format is picked from the extension in the
+/// same way as `load_top_level_from_file`. The file is not locked, so use a
+/// `Transaction` instead if other writers may edit it between loading and writing.
+pub fn write_top_level_to_file(top_level: &TopLevel, file: &Path) -> Result<(),ProcedureError> {
+    let file_name = match file.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return Err(ProcedureError::WriteFailed(io::ErrorKind::InvalidInput)),
+    };
+    // The counter keeps threads of one process from sharing a temporary file.
+    static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let tmp = file.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+    ));
+    let result = write_and_sync(top_level, &tmp, FileFormat::from_path(file))
+        .and_then(|_| replace_file(&tmp, file).map_err(|e| ProcedureError::WriteFailed(e.kind())));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn write_and_sync(top_level: &TopLevel, path: &Path, format: FileFormat) -> Result<(),ProcedureError> {
+    let f = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+    match format {
+        FileFormat::Json => write_top_level_to_writer(top_level, BufWriter::new(&f))?,
+        #[cfg(feature = "gzip")]
+        FileFormat::GzipJson => {
+            let mut encoder = flate2::write::GzEncoder::new(BufWriter::new(&f), flate2::Compression::default());
+            write_top_level_to_writer(top_level, &mut encoder)?;
+            encoder
+                .finish()
+                .and_then(|mut writer| writer.flush())
+                .map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+        }
+        #[cfg(feature = "toml")]
+        FileFormat::Toml => {
+            let s = toml::to_string_pretty(top_level).map_err(|e| ProcedureError::SerializationFailed(e.to_string()))?;
+            (&f).write_all(s.as_bytes()).map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+        }
+        #[cfg(feature = "yaml")]
+        FileFormat::Yaml => {
+            let mut writer = BufWriter::new(&f);
+            serde_yaml::to_writer(&mut writer, top_level)
+                .map_err(|e| ProcedureError::SerializationFailed(e.to_string()))?;
+            writer.flush().map_err(|e| ProcedureError::WriteFailed(e.kind()))?;
+        }
+    }
+    f.sync_all().map_err(|e| ProcedureError::WriteFailed(e.kind()))
+}
+
+#[cfg(not(windows))]
+fn replace_file(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::rename(from, to)
+}
+
+/// On Windows renaming over an existing file can fail transiently while another
+/// process (a virus scanner, an editor) holds the target open, so retry a few times.
+#[cfg(windows)]
+fn replace_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut attempts = 0;
+    loop {
+        match std::fs::rename(from, to) {
+            Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied && attempts < 10 => {
+                attempts += 1;
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Returns a hashmap of all benchmark sets contained within this meta set, as well
+/// as any benchmark sets found recursively within any meta sets contained within
+/// this meta set. Returns Err if the file cannot be loaded, rather than panicking.
+pub fn get_benchmarks_from_meta(
+    meta_set_key: &str,
+    file: &Path,
+) -> Result<HashMap<String, BenchmarkSet>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level.resolve_meta(meta_set_key))
+}
+
+/// The meta walks below use an explicit stack rather than recursion, so a very deep
+/// chain of meta sets can't overflow the call stack.
+fn walk_meta_for_benchmarks(root: &str, top_level: &TopLevel) -> HashMap<String, BenchmarkSet> {
+    let mut seen_keys = HashSet::new();
+    let mut current_benchmark_sets = HashMap::new();
+    let mut stack = vec![root];
+    while let Some(key) = stack.pop() {
+        if !seen_keys.insert(key) {
+            continue;
+        }
+        let members = top_level.meta_sets.get(key);
+        let set = top_level.benchmark_sets.get(key);
+        if members.is_none() && set.is_none() && key != root {
+            warn!("dangling reference {:?}: neither a benchmark set nor a meta set", key);
+        }
+        if let Some(members) = members {
+            stack.extend(members.iter().map(String::as_str));
+        }
+        if let Some(set) = set {
+            current_benchmark_sets.insert(key.to_string(), set.to_owned());
+        }
+    }
+    current_benchmark_sets
+}
+
+/// Returns all meta sets that are a part of this meta set, recursively. Returns
+/// Err if the file cannot be loaded.
+pub fn get_metas_from_meta(
+    meta_set_key: &str,
+    file: &Path,
+) -> Result<BTreeSet<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(walk_meta_for_metas(meta_set_key, &top_level))
+}
+
+fn walk_meta_for_metas(key: &str, top_level: &TopLevel) -> BTreeSet<String> {
+    let mut current_meta_sets = BTreeSet::new();
+    let mut stack = vec![key];
+    while let Some(key) = stack.pop() {
+        if current_meta_sets.contains(key) {
+            continue;
+        }
+        if let Some(members) = top_level.meta_sets.get(key) {
+            current_meta_sets.insert(key.to_string());
+            stack.extend(members.iter().map(String::as_str));
+        }
+    }
+    current_meta_sets
+}
+
+/// Returns every cycle found among the meta sets. Each cycle is the ordered path of
+/// meta set names that leads back to its start, with the starting name repeated at
+/// the end, e.g. `["a", "b", "a"]`. Meta sets are visited in sorted order, so the
+/// same file always yields the same cycles in the same order. One cycle is reported
+/// for each edge that closes a loop, so every loop in the graph is broken by some
+/// reported cycle, but not every distinct path around a loop is listed.
+pub fn find_meta_cycles(file: &Path) -> Result<Vec<Vec<String>>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let mut finished = BTreeSet::new();
+    let mut cycles = Vec::new();
+    for key in top_level.meta_sets.keys() {
+        let mut on_cycle = |cycle| {
+            cycles.push(cycle);
+            Ok(())
+        };
+        walk_meta_depth_first(key, &top_level, &mut finished, &mut on_cycle, &mut |_| {})?;
+    }
+    Ok(cycles)
+}
+
+/// Walks the meta sets depth first from `root`. `on_cycle` gets the path from a meta
+/// set back to itself, start repeated at the end, for each member that closes a loop;
+/// an Err from it stops the walk. `on_finish` gets each meta set once all of its
+/// members have been walked, so leaves come first. Meta sets already in `finished` are
+/// skipped, so sharing it between calls walks each meta set only once.
+fn walk_meta_depth_first<'a>(
+    root: &'a str,
+    top_level: &'a TopLevel,
+    finished: &mut BTreeSet<&'a str>,
+    on_cycle: &mut dyn FnMut(Vec<String>) -> Result<(),ProcedureError>,
+    on_finish: &mut dyn FnMut(&'a str),
+) -> Result<(),ProcedureError> {
+    // Each entry is a meta set on the current path and the members still to walk.
+    // `on_path` mirrors the names in `path` so that spotting a loop doesn't need a scan.
+    let mut path: Vec<(&'a str, std::collections::btree_set::Iter<'a, String>)> = Vec::new();
+    let mut on_path = HashSet::new();
+    let mut next = Some(root);
+    loop {
+        if let Some(key) = next.take().filter(|key| !finished.contains(key)) {
+            if on_path.contains(key) {
+                let start = path.iter().position(|(k, _)| *k == key).unwrap_or(0);
+                let mut cycle: Vec<String> = path[start..].iter().map(|(k, _)| k.to_string()).collect();
+                cycle.push(key.to_string());
+                debug!("meta sets form a cycle: {}", cycle.join(" -> "));
+                on_cycle(cycle)?;
+            } else if let Some(members) = top_level.meta_sets.get(key) {
+                path.push((key, members.iter()));
+                on_path.insert(key);
+            }
+        }
+        let (key, members) = match path.last_mut() {
+            Some(top) => top,
+            None => return Ok(()),
+        };
+        match members.next() {
+            Some(member) => next = Some(member),
+            None => {
+                let key = *key;
+                path.pop();
+                on_path.remove(key);
+                finished.insert(key);
+                on_finish(key);
+            }
+        }
+    }
+}
+
+/// Returns every meta set member that names neither a benchmark set nor a meta set,
+/// as `(meta_name, missing_member)` pairs sorted by meta name and then member.
+pub fn find_dangling_references(file: &Path) -> Result<Vec<(String, String)>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let mut dangling = Vec::new();
+    for (meta, members) in &top_level.meta_sets {
+        for member in members {
+            if !top_level.benchmark_sets.contains_key(member) && !top_level.meta_sets.contains_key(member) {
+                dangling.push((meta.to_string(), member.to_string()));
+            }
+        }
+    }
+    Ok(dangling)
+}
+
+/// Returns the benchmark sets that are not a member of any meta set, in sorted order.
+/// Every meta set counts as an entry point, including one that no other meta set
+/// lists, so a benchmark set reachable from any meta set is not an orphan. Use
+/// `find_orphan_sets_from` to name the entry points instead.
+pub fn find_orphan_sets(file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let referenced: HashSet<&String> = top_level.meta_sets.values().flatten().collect();
+    Ok(top_level
+        .benchmark_sets
+        .keys()
+        .filter(|name| !referenced.contains(name))
+        .cloned()
+        .collect())
+}
+
+/// Returns the benchmark sets not reachable from any of the supplied meta sets, in
+/// sorted order. A meta set that can't be reached from the roots is orphaned as well,
+/// so the sets only it lists are orphans. Roots that are not meta sets reach nothing.
+pub fn find_orphan_sets_from(roots: &[&str], file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let reachable: HashSet<String> = roots
+        .iter()
+        .filter(|root| top_level.meta_sets.contains_key(**root))
+        .flat_map(|root| walk_meta_for_benchmarks(root, &top_level).into_keys())
+        .collect();
+    Ok(top_level
+        .benchmark_sets
+        .keys()
+        .filter(|name| !reachable.contains(*name))
+        .cloned()
+        .collect())
+}
+
+/// Returns the names of the benchmark sets containing a map with the supplied sha256,
+/// compared case-insensitively, in sorted order.
+pub fn find_sets_using_map(sha256: &str, file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level
+        .benchmark_sets
+        .iter()
+        .filter(|(_, set)| set.contains_map_sha(sha256))
+        .map(|(name, _)| name.to_string())
+        .collect())
+}
+
+/// Returns the names of the benchmark sets containing a mod with the supplied name,
+/// in sorted order. If a version is supplied, only that version of the mod matches.
+pub fn find_sets_using_mod(
+    name: &str,
+    version: Option<&str>,
+    file: &Path,
+) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level
+        .benchmark_sets
+        .iter()
+        .filter(|(_, set)| {
+            set.mods
+                .iter()
+                .any(|m| m.name == name && version.is_none_or(|v| m.version == v))
+        })
+        .map(|(set_name, _)| set_name.to_string())
+        .collect())
+}
+
+/// How `merge_files` resolves a set that is present in both files with different contents.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MergeStrategy {
+    PreferBase,
+    PreferIncoming,
+    /// Refuse to merge, returning every conflicting name.
+    Error,
+}
+
+/// Which file a conflicting set was taken from.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MergeSide {
+    Base,
+    Incoming,
+}
+
+/// The conflicts found by `merge_files` and which side won each. Sets that were
+/// identical in both files are not conflicts.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MergeReport {
+    pub benchmark_conflicts: Vec<(String, MergeSide)>,
+    pub meta_conflicts: Vec<(String, MergeSide)>,
+}
+
+/// Merges the benchmark sets and meta sets of two files and writes the result to `out`,
+/// which may be either of the inputs. Sets present in only one file are always kept.
+pub fn merge_files(
+    base: &Path,
+    incoming: &Path,
+    strategy: MergeStrategy,
+    out: &Path,
+) -> Result<MergeReport,ProcedureError> {
+    // Taken before loading, as `out` may be one of the inputs.
+    let _lock = lock_for_write(out)?;
+    let mut top_level = load_top_level_from_file(base)?;
+    let other = load_top_level_from_file(incoming)?;
+    info!("merging {} into {}", incoming.display(), base.display());
+    if strategy == MergeStrategy::Error {
+        let mut conflicts = conflicting_keys(&top_level.benchmark_sets, &other.benchmark_sets);
+        conflicts.extend(conflicting_keys(&top_level.meta_sets, &other.meta_sets));
+        if !conflicts.is_empty() {
+            warn!("refusing to merge, conflicting sets: {:?}", conflicts);
+            return Err(ProcedureError::ProceduresAlreadyExist(conflicts));
+        }
+    }
+    let report = MergeReport {
+        benchmark_conflicts: merge_keys(&mut top_level.benchmark_sets, other.benchmark_sets, strategy),
+        meta_conflicts: merge_keys(&mut top_level.meta_sets, other.meta_sets, strategy),
+    };
+    write_top_level_to_file(&top_level, out)?;
+    info!("wrote merged sets to {}", out.display());
+    Ok(report)
+}
+
+fn conflicting_keys<V: PartialEq>(base: &BTreeMap<String, V>, incoming: &BTreeMap<String, V>) -> Vec<String> {
+    incoming
+        .iter()
+        .filter(|(k, v)| base.get(*k).is_some_and(|b| b != *v))
+        .map(|(k, _)| k.to_string())
+        .collect()
+}
+
+fn merge_keys<V: PartialEq>(
+    base: &mut BTreeMap<String, V>,
+    incoming: BTreeMap<String, V>,
+    strategy: MergeStrategy,
+) -> Vec<(String, MergeSide)> {
+    let mut conflicts = Vec::new();
+    for (k, v) in incoming {
+        match base.get(&k) {
+            None => {
+                base.insert(k, v);
+            }
+            Some(b) if *b == v => {}
+            Some(_) => {
+                if strategy == MergeStrategy::PreferIncoming {
+                    debug!("conflict on {:?}: took the incoming version", k);
+                    conflicts.push((k.clone(), MergeSide::Incoming));
+                    base.insert(k, v);
+                } else {
+                    debug!("conflict on {:?}: kept the base version", k);
+                    conflicts.push((k, MergeSide::Base));
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+/// Writes a new file at `dst` holding only what is needed to run the named set from
+/// `src`, replacing anything already at `dst`. For a benchmark set that is just the
+/// set. For a meta set it is the meta set, every meta set it reaches and every
+/// benchmark set those resolve to, with the meta sets kept as they are so the
+/// structure survives. Members that don't resolve to anything in `src` are kept as
+/// dangling references. Returns Err if `name` is neither kind of set.
+pub fn export_set(name: &str, src: &Path, dst: &Path) -> Result<(),ProcedureError> {
+    let top_level = load_top_level_from_file(src)?;
+    let mut exported = TopLevel::default();
+    match top_level.classify(name) {
+        NameKind::Unknown => return Err(ProcedureError::SetNotPresent),
+        NameKind::Benchmark => {}
+        NameKind::Meta | NameKind::Both => {
+            for meta in walk_meta_for_metas(name, &top_level) {
+                exported.meta_sets.insert(meta.clone(), top_level.meta_sets[&meta].clone());
+            }
+            exported.benchmark_sets.extend(top_level.resolve_meta(name));
+        }
+    }
+    if let Some(set) = top_level.benchmark_sets.get(name) {
+        exported.benchmark_sets.insert(name.to_string(), set.clone());
+    }
+    let _lock = lock_for_write(dst)?;
+    write_top_level_to_file(&exported, dst)
+}
+
+/// What `import_file` did with each set of the source file, by name.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ImportReport {
+    pub added_benchmark_sets: Vec<String>,
+    pub skipped_benchmark_sets: Vec<String>,
+    pub overwritten_benchmark_sets: Vec<String>,
+    pub added_meta_sets: Vec<String>,
+    pub skipped_meta_sets: Vec<String>,
+    pub overwritten_meta_sets: Vec<String>,
+}
+
+/// Copies every benchmark set and meta set of `src` into `dst`, creating `dst` if it
+/// doesn't exist. A name already taken in `dst` is replaced if `overwrite` is true and
+/// skipped otherwise, for both kinds of set. A set identical to the one already in
+/// `dst` is always reported as skipped, since nothing changes.
+pub fn import_file(src: &Path, dst: &Path, overwrite: ProcedureOverwrite) -> Result<ImportReport,ProcedureError> {
+    let incoming = load_top_level_from_file(src)?;
+    let _lock = lock_for_write(dst)?;
+    let mut top_level = load_top_level_or_default(dst)?;
+    let (added_benchmark_sets, skipped_benchmark_sets, overwritten_benchmark_sets) =
+        import_keys(&mut top_level.benchmark_sets, incoming.benchmark_sets, overwrite);
+    let (added_meta_sets, skipped_meta_sets, overwritten_meta_sets) =
+        import_keys(&mut top_level.meta_sets, incoming.meta_sets, overwrite);
+    let report = ImportReport {
+        added_benchmark_sets,
+        skipped_benchmark_sets,
+        overwritten_benchmark_sets,
+        added_meta_sets,
+        skipped_meta_sets,
+        overwritten_meta_sets,
+    };
+    write_top_level_to_file(&top_level, dst)?;
+    info!(
+        "imported {} into {}: {} added, {} skipped, {} overwritten",
+        src.display(),
+        dst.display(),
+        report.added_benchmark_sets.len() + report.added_meta_sets.len(),
+        report.skipped_benchmark_sets.len() + report.skipped_meta_sets.len(),
+        report.overwritten_benchmark_sets.len() + report.overwritten_meta_sets.len()
+    );
+    Ok(report)
+}
+
+/// Moves `incoming` into `base`, returning the names that were added, skipped and
+/// overwritten, in that order.
+fn import_keys<V: PartialEq>(
+    base: &mut BTreeMap<String, V>,
+    incoming: BTreeMap<String, V>,
+    overwrite: ProcedureOverwrite,
+) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let (mut added, mut skipped, mut overwritten) = (Vec::new(), Vec::new(), Vec::new());
+    for (k, v) in incoming {
+        match base.get(&k) {
+            None => {
+                debug!("imported {:?}", k);
+                added.push(k.clone());
+                base.insert(k, v);
+            }
+            Some(existing) if *existing == v || overwrite == false.into() => {
+                debug!("skipped {:?}: already exists", k);
+                skipped.push(k);
+            }
+            Some(_) => {
+                debug!("overwrote {:?}", k);
+                overwritten.push(k.clone());
+                base.insert(k, v);
+            }
+        }
+    }
+    (added, skipped, overwritten)
+}
+
+/// The structural difference between two `TopLevel`s, as produced by `diff`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TopLevelDiff {
+    pub added_benchmark_sets: Vec<String>,
+    pub removed_benchmark_sets: Vec<String>,
+    pub changed_benchmark_sets: BTreeMap<String, BenchmarkSetDiff>,
+    pub added_meta_sets: Vec<String>,
+    pub removed_meta_sets: Vec<String>,
+    pub changed_meta_sets: Vec<String>,
+}
+
+/// The field-level difference between two versions of a benchmark set. Fields other
+/// than maps and mods hold `(old, new)` when they changed.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct BenchmarkSetDiff {
+    pub save_subdirectory: Option<(Option<PathBuf>, Option<PathBuf>)>,
+    pub ticks: Option<(u32, u32)>,
+    pub runs: Option<(u32, u32)>,
+    pub factorio_version: Option<(Option<String>, Option<String>)>,
+    pub tags: Option<(BTreeSet<String>, BTreeSet<String>)>,
+    pub description: Option<(Option<String>, Option<String>)>,
+    pub created: Option<(Option<String>, Option<String>)>,
+    pub modified: Option<(Option<String>, Option<String>)>,
+    pub extra: Option<(BTreeMap<String, serde_json::Value>, BTreeMap<String, serde_json::Value>)>,
+    pub added_maps: BTreeSet<Map>,
+    pub removed_maps: BTreeSet<Map>,
+    pub added_mods: BTreeSet<Mod>,
+    pub removed_mods: BTreeSet<Mod>,
+}
+
+/// Compares two `TopLevel`s. Maps and mods are matched using their sha based equality,
+/// so a renamed map with the same sha256 is not reported as added or removed.
+pub fn diff(old: &TopLevel, new: &TopLevel) -> TopLevelDiff {
+    let mut diff = TopLevelDiff::default();
+    for (name, set) in &new.benchmark_sets {
+        match old.benchmark_sets.get(name) {
+            None => diff.added_benchmark_sets.push(name.to_string()),
+            Some(old_set) if old_set != set => {
+                diff.changed_benchmark_sets.insert(name.to_string(), diff_benchmark_sets(old_set, set));
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed_benchmark_sets = old
+        .benchmark_sets
+        .keys()
+        .filter(|name| !new.benchmark_sets.contains_key(*name))
+        .cloned()
+        .collect();
+    for (name, members) in &new.meta_sets {
+        match old.meta_sets.get(name) {
+            None => diff.added_meta_sets.push(name.to_string()),
+            Some(old_members) if old_members != members => diff.changed_meta_sets.push(name.to_string()),
+            Some(_) => {}
+        }
+    }
+    diff.removed_meta_sets = old
+        .meta_sets
+        .keys()
+        .filter(|name| !new.meta_sets.contains_key(*name))
+        .cloned()
+        .collect();
+    diff
+}
+
+fn diff_benchmark_sets(old: &BenchmarkSet, new: &BenchmarkSet) -> BenchmarkSetDiff {
+    BenchmarkSetDiff {
+        save_subdirectory: changed(&old.save_subdirectory, &new.save_subdirectory),
+        ticks: changed(&old.ticks, &new.ticks),
+        runs: changed(&old.runs, &new.runs),
+        factorio_version: changed(&old.factorio_version, &new.factorio_version),
+        tags: changed(&old.tags, &new.tags),
+        description: changed(&old.description, &new.description),
+        created: changed(&old.created, &new.created),
+        modified: changed(&old.modified, &new.modified),
+        extra: changed(&old.extra, &new.extra),
+        added_maps: items_not_in(&new.maps, &old.maps),
+        removed_maps: items_not_in(&old.maps, &new.maps),
+        added_mods: items_not_in(&new.mods, &old.mods),
+        removed_mods: items_not_in(&old.mods, &new.mods),
+    }
+}
+
+fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<(T, T)> {
+    if old == new {
+        None
+    } else {
+        Some((old.clone(), new.clone()))
+    }
+}
+
+/// Returns the items of `a` that are not equal to any item of `b`. This uses
+/// `PartialEq` rather than `BTreeSet::contains`, which goes by `Ord`.
+fn items_not_in<T: PartialEq + Ord + Clone>(a: &BTreeSet<T>, b: &BTreeSet<T>) -> BTreeSet<T> {
+    a.iter().filter(|x| !b.iter().any(|y| y == *x)).cloned().collect()
+}
+
+/// Returns the total number of map runs a meta set resolves to, summing
+/// `maps * runs` over each benchmark set reached. A benchmark set reached through
+/// several paths is only counted once. Returns Err if the meta set is not present.
+pub fn total_runs_for_meta(meta_key: &str, file: &Path) -> Result<u64,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(sets_reachable_from(&top_level, meta_key)?
+        .into_iter()
+        .map(|set| set.maps.len() as u64 * u64::from(set.runs))
+        .sum())
+}
+
+/// Returns the total number of ticks a meta set resolves to, summing
+/// `maps * ticks * runs` over each benchmark set reached, for a rough estimate of
+/// wall clock time. A benchmark set reached through several paths is only counted once.
+/// Returns Err if the meta set is not present.
+pub fn total_ticks_for_meta(meta_key: &str, file: &Path) -> Result<u64,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(sets_reachable_from(&top_level, meta_key)?
+        .into_iter()
+        .map(|set| set.maps.len() as u64 * u64::from(set.ticks) * u64::from(set.runs))
+        .sum())
+}
+
+/// Multiplies `runs` by `factor` on every benchmark set reachable from a meta set,
+/// rounding up and keeping at least 1 run, and writes the file back. A set reached
+/// through several paths is only scaled once. Returns Err if the meta set is not
+/// present.
+pub fn scale_runs_in_meta(meta_key: &str, factor: f64, file: &Path) -> Result<(),ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    for name in reachable_benchmark_sets(&top_level, meta_key)? {
+        if let Some(set) = top_level.benchmark_sets.get_mut(&name) {
+            let runs = scaled_runs(set.runs, factor);
+            if runs != set.runs {
+                set.runs = runs;
+                set.modified = Some(now_rfc3339());
+            }
+        }
+    }
+    write_top_level_to_file(&top_level, file)
+}
+
+/// Multiplies `runs` by `factor`, rounding up and keeping at least 1. A product within
+/// float error of a whole number is taken as that number, so `100 * 1.1` is 110 rather
+/// than 111.
+fn scaled_runs(runs: u32, factor: f64) -> u32 {
+    let product = f64::from(runs) * factor;
+    let nearest = product.round();
+    let product = if (product - nearest).abs() <= 1e-9 * nearest.abs().max(1.0) {
+        nearest
+    } else {
+        product.ceil()
+    };
+    // The cast saturates, so a negative or NaN factor ends up at the minimum.
+    (product as u32).max(1)
+}
+
+/// Overwrites `ticks` and/or `runs` on every benchmark set reachable from a meta set,
+/// leaving a field alone when given `None`, and writes the file back. Returns how many
+/// sets actually changed, or Err if the meta set is not present.
+pub fn set_ticks_runs_in_meta(
+    meta_key: &str,
+    ticks: Option<u32>,
+    runs: Option<u32>,
+    file: &Path,
+) -> Result<usize,ProcedureError> {
+    let _lock = lock_for_write(file)?;
+    let mut top_level = load_top_level_from_file(file)?;
+    let mut changed = 0;
+    for name in reachable_benchmark_sets(&top_level, meta_key)? {
+        if let Some(set) = top_level.benchmark_sets.get_mut(&name) {
+            let new_ticks = ticks.unwrap_or(set.ticks);
+            let new_runs = runs.unwrap_or(set.runs);
+            if (new_ticks, new_runs) != (set.ticks, set.runs) {
+                set.ticks = new_ticks;
+                set.runs = new_runs;
+                set.modified = Some(now_rfc3339());
+                changed += 1;
+            }
+        }
+    }
+    if changed > 0 {
+        write_top_level_to_file(&top_level, file)?;
+    }
+    Ok(changed)
+}
+
+/// The names of the benchmark sets reachable from a meta set, in sorted order, or Err
+/// if the meta set is not present.
+fn reachable_benchmark_sets(top_level: &TopLevel, meta_key: &str) -> Result<BTreeSet<String>,ProcedureError> {
+    if !top_level.meta_sets.contains_key(meta_key) {
+        return Err(ProcedureError::SetNotPresent);
+    }
+    Ok(top_level.resolve_meta(meta_key).into_keys().collect())
+}
+
+/// The benchmark sets reachable from a meta set, in name order, or Err if the meta set
+/// is not present.
+fn sets_reachable_from<'a>(top_level: &'a TopLevel, meta_key: &str) -> Result<Vec<&'a BenchmarkSet>,ProcedureError> {
+    let names = reachable_benchmark_sets(top_level, meta_key)?;
+    Ok(names.iter().filter_map(|name| top_level.benchmark_sets.get(name)).collect())
+}
+
+/// How many bytes `Map::download` lets pass between progress reports.
+#[cfg(feature = "download")]
+pub const DOWNLOAD_PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// How many maps `download_missing_maps` fetches at once.
+#[cfg(feature = "download")]
+const DOWNLOAD_CONCURRENCY: usize = 4;
+
+/// Downloads every map of a set that is not already present and intact in the set's
+/// save directory under `base`, returning the names of the maps fetched. Up to
+/// `DOWNLOAD_CONCURRENCY` maps are downloaded at once, and each is hashed again once
+/// it is on disk. If any download fails, the first failure in map order is returned,
+/// after the other downloads have finished.
+#[cfg(feature = "download")]
+pub fn download_missing_maps(set: &BenchmarkSet, base: &Path) -> Result<Vec<String>, DownloadError> {
+    let dir = set.resolved_save_dir(base);
+    std::fs::create_dir_all(&dir)?;
+    let mut missing = Vec::new();
+    for map in &set.maps {
+        let dest = map.resolved_path(&dir);
+        if !dest.exists() || !map.verify_file(&dest)? {
+            missing.push((map, dest));
+        }
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..DOWNLOAD_CONCURRENCY.min(missing.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, AtomicOrdering::SeqCst);
+                let (map, dest) = match missing.get(i) {
+                    Some(item) => item,
+                    None => break,
+                };
+                let result = map.download(dest, None).and_then(|_| {
+                    let actual = map.hash_algo.compute(dest)?;
+                    if hashes_match(&actual, &map.sha256) {
+                        Ok(())
+                    } else {
+                        Err(DownloadError::HashMismatch {
+                            expected: map.sha256.to_string(),
+                            actual,
+                        })
+                    }
+                });
+                results.lock().unwrap().push((i, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    let mut fetched = Vec::new();
+    for (i, result) in results {
+        result?;
+        fetched.push(missing[i].0.name.to_string());
+    }
+    Ok(fetched)
+}
+
+/// The outcome of `verify_set`, listing map and mod names by result.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct VerifyReport {
+    pub ok_maps: Vec<String>,
+    pub missing_maps: Vec<String>,
+    pub mismatched_maps: Vec<String>,
+    pub ok_mods: Vec<String>,
+    pub missing_mods: Vec<String>,
+    pub mismatched_mods: Vec<String>,
+    /// Mods with no recorded sha1, which can't be checked.
+    pub unhashed_mods: Vec<String>,
+}
+
+impl VerifyReport {
+    /// True if every map and mod was found and matched its hash.
+    pub fn is_ok(&self) -> bool {
+        self.missing_maps.is_empty()
+            && self.mismatched_maps.is_empty()
+            && self.missing_mods.is_empty()
+            && self.mismatched_mods.is_empty()
+            && self.unhashed_mods.is_empty()
+    }
+}
+
+/// Checks every map of a set against the set's save directory under `saves_dir`, and
+/// every mod against its zip in `mods_dir`. Missing files and hash mismatches are
+/// recorded in the report rather than stopping the check; only other IO errors
+/// abort it.
+pub fn verify_set(set: &BenchmarkSet, saves_dir: &Path, mods_dir: &Path) -> Result<VerifyReport, io::Error> {
+    let mut report = VerifyReport::default();
+    let save_dir = set.resolved_save_dir(saves_dir);
+    for map in &set.maps {
+        let path = map.resolved_path(&save_dir);
+        let name = map.name.to_string();
+        if !path.exists() {
+            report.missing_maps.push(name);
+        } else if map.verify_file(&path)? {
+            report.ok_maps.push(name);
+        } else {
+            report.mismatched_maps.push(name);
+        }
+    }
+    for m in &set.mods {
+        let path = mods_dir.join(m.expected_file_name());
+        let name = m.name.to_string();
+        if !path.exists() {
+            report.missing_mods.push(name);
+        } else if m.sha1.is_empty() {
+            report.unhashed_mods.push(name);
+        } else if m.verify(&path)? {
+            report.ok_mods.push(name);
+        } else {
+            report.mismatched_mods.push(name);
+        }
+    }
+    Ok(report)
+}
+
+/// Hashes every map of a set against the set's save directory under `saves_dir`,
+/// returning each map's name with whether it matched, in map order. A missing file
+/// shows up as a `NotFound` error for that map. With the `rayon` feature the maps are
+/// hashed in parallel, otherwise one after another.
+pub fn verify_maps_parallel(set: &BenchmarkSet, saves_dir: &Path) -> Vec<(String, Result<bool, io::Error>)> {
+    let save_dir = set.resolved_save_dir(saves_dir);
+    let verify = |map: &Map| (map.name.to_string(), map.verify_file(&map.resolved_path(&save_dir)));
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        let maps: Vec<&Map> = set.maps.iter().collect();
+        maps.into_par_iter().map(verify).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        set.maps.iter().map(verify).collect()
+    }
+}
+
+/// Renders the meta sets as a Graphviz DOT digraph, with an edge from each meta set
+/// to each of its members. Meta sets are drawn as boxes and benchmark sets as
+/// ellipses; a name that is both is a box with a double border, and a member that is
+/// neither is a dashed ellipse. Cycles are drawn like any other edges.
+pub fn meta_graph_dot(file: &Path) -> Result<String,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let mut nodes: BTreeSet<&String> = top_level.benchmark_sets.keys().collect();
+    nodes.extend(top_level.meta_sets.keys());
+    nodes.extend(top_level.meta_sets.values().flatten());
+    let mut dot = String::from("digraph meta_sets {\n");
+    for node in nodes {
+        let style = match top_level.classify(node) {
+            NameKind::Both => "shape=box, peripheries=2",
+            NameKind::Meta => "shape=box",
+            NameKind::Benchmark => "shape=ellipse",
+            NameKind::Unknown => "shape=ellipse, style=dashed",
+        };
+        dot.push_str(&format!("    {} [{}];\n", dot_id(node), style));
+    }
+    for (meta, members) in &top_level.meta_sets {
+        for member in members {
+            dot.push_str(&format!("    {} -> {};\n", dot_id(meta), dot_id(member)));
+        }
+    }
+    dot.push_str("}\n");
+    Ok(dot)
+}
+
+/// Quotes a name for use as a DOT node id.
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes one CSV row per benchmark set, in name order, after a header row with the
+/// columns `name,ticks,runs,map_count,mod_count`. A name containing a comma, quote or
+/// line break is quoted, with any quotes doubled, so spreadsheets read it back intact.
+pub fn export_csv(file: &Path, out: &mut dyn Write) -> Result<(),ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let mut csv = String::from("name,ticks,runs,map_count,mod_count\n");
+    for (name, set) in &top_level.benchmark_sets {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(name),
+            set.ticks,
+            set.runs,
+            set.maps.len(),
+            set.mods.len()
+        ));
+    }
+    out.write_all(csv.as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| ProcedureError::WriteFailed(e.kind()))
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Orders the meta sets so that every meta set comes after the meta sets it contains,
+/// leaves first. Ties are broken by name, so the order is deterministic. Returns
+/// `MetaCycle` with the first cycle found if there is no such order.
+pub fn topo_sort_metas(file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    let mut finished = BTreeSet::new();
+    let mut order = Vec::new();
+    for key in top_level.meta_sets.keys() {
+        let mut on_finish = |key: &str| order.push(key.to_string());
+        walk_meta_depth_first(
+            key,
+            &top_level,
+            &mut finished,
+            &mut |cycle| Err(ProcedureError::MetaCycle(cycle)),
+            &mut on_finish,
+        )?;
+    }
+    Ok(order)
+}
+
+/// Returns every distinct map used by the benchmark sets a meta set resolves to.
+/// Maps are deduplicated by sha256; when several sets name the same save
+/// differently, the entry from the first set in name order is kept. Returns Err if
+/// the meta set is not present.
+pub fn unique_maps_in_meta(meta_key: &str, file: &Path) -> Result<BTreeSet<Map>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(unique_items(sets_reachable_from(&top_level, meta_key)?.into_iter().flat_map(|set| &set.maps)))
+}
+
+/// Returns every distinct mod used by the benchmark sets a meta set resolves to,
+/// deduplicated by `Mod`'s equality. Returns Err if the meta set is not present.
+pub fn unique_mods_in_meta(meta_key: &str, file: &Path) -> Result<BTreeSet<Mod>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(unique_items(sets_reachable_from(&top_level, meta_key)?.into_iter().flat_map(|set| &set.mods)))
+}
+
+/// Returns the disk space in bytes the maps of a meta set need, counting each save
+/// once however many sets use it. Returns `MissingMapSizes` listing every map without
+/// a recorded `size_bytes` rather than an estimate that is too low, and Err if the meta
+/// set is not present.
+pub fn meta_disk_size(meta_key: &str, file: &Path) -> Result<u64,ProcedureError> {
+    let maps = unique_maps_in_meta(meta_key, file)?;
+    let unsized_maps: Vec<String> = maps
+        .iter()
+        .filter(|map| map.size_bytes.is_none())
+        .map(|map| map.name.to_string())
+        .collect();
+    if !unsized_maps.is_empty() {
+        return Err(ProcedureError::MissingMapSizes(unsized_maps));
+    }
+    Ok(maps.iter().filter_map(|map| map.size_bytes).sum())
+}
+
+/// Returns the names of the benchmark sets reachable from both meta sets, or Err if
+/// either meta set is not present.
+pub fn meta_intersection(a: &str, b: &str, file: &Path) -> Result<BTreeSet<String>,ProcedureError> {
+    let (a, b) = resolve_meta_names(a, b, file)?;
+    Ok(a.intersection(&b).cloned().collect())
+}
+
+/// Returns the names of the benchmark sets reachable from meta set `a` but not from `b`,
+/// or Err if either meta set is not present.
+pub fn meta_difference(a: &str, b: &str, file: &Path) -> Result<BTreeSet<String>,ProcedureError> {
+    let (a, b) = resolve_meta_names(a, b, file)?;
+    Ok(a.difference(&b).cloned().collect())
+}
+
+fn resolve_meta_names(a: &str, b: &str, file: &Path) -> Result<(BTreeSet<String>, BTreeSet<String>),ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok((reachable_benchmark_sets(&top_level, a)?, reachable_benchmark_sets(&top_level, b)?))
+}
+
+/// Collects items, keeping only the first of any that are equal by `PartialEq`.
+fn unique_items<'a, T: 'a + Hash + Eq + Ord + Clone>(items: impl Iterator<Item = &'a T>) -> BTreeSet<T> {
+    let mut seen = HashSet::new();
+    items.filter(|item| seen.insert(*item)).cloned().collect()
+}
+
+/// Returns the names of the benchmark sets carrying exactly this tag, compared case
+/// sensitively, in sorted order.
+pub fn find_sets_by_tag(tag: &str, file: &Path) -> Result<Vec<String>,ProcedureError> {
+    let top_level = load_top_level_from_file(file)?;
+    Ok(top_level
+        .benchmark_sets
+        .iter()
+        .filter(|(_, set)| set.tags.contains(tag))
+        .map(|(name, _)| name.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a path in a fresh temporary directory, which is removed once the guard
+    /// is dropped.
+    struct TempFile {
+        dir: PathBuf,
+        path: PathBuf,
+    }
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "fbhf-test-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(name);
+            TempFile { dir, path }
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn set(ticks: u32, runs: u32) -> BenchmarkSet {
+        BenchmarkSet {
+            ticks,
+            runs,
+            ..BenchmarkSet::default()
+        }
+    }
+
+    #[test]
+    fn write_refuses_to_replace_a_file_that_fails_to_parse() {
+        let file = TempFile::new("sets.json");
+        write_benchmark_set_to_file("keep", set(100, 1), ProcedureOverwrite::False, &file.path).unwrap();
+        let mut contents = std::fs::read_to_string(&file.path).unwrap();
+        contents.push(',');
+        std::fs::write(&file.path, &contents).unwrap();
+
+        let result = write_benchmark_set_to_file("new", set(100, 1), ProcedureOverwrite::False, &file.path);
+        assert!(matches!(result, Err(ProcedureError::MalformedJSON(_))), "{:?}", result);
+        assert_eq!(std::fs::read_to_string(&file.path).unwrap(), contents);
+    }
+
+    #[test]
+    fn write_creates_a_missing_file() {
+        let file = TempFile::new("sets.json");
+        write_benchmark_set_to_file("new", set(100, 1), ProcedureOverwrite::False, &file.path).unwrap();
+        let top_level = load_top_level_from_file(&file.path).unwrap();
+        assert_eq!(top_level.benchmark_sets.keys().collect::<Vec<_>>(), ["new"]);
+    }
+
+    fn members(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn rename_rewrites_meta_references() {
+        let file = TempFile::new("sets.json");
+        let mut top_level = TopLevel::default();
+        top_level.benchmark_sets.insert("old".to_string(), set(100, 1));
+        top_level.benchmark_sets.insert("other".to_string(), set(100, 1));
+        top_level.meta_sets.insert("parent".to_string(), members(&["old", "other"]));
+        write_top_level_to_file(&top_level, &file.path).unwrap();
+
+        rename_benchmark_set("old", "new", &file.path).unwrap();
+        let top_level = load_top_level_from_file(&file.path).unwrap();
+        assert!(top_level.benchmark_sets.contains_key("new"));
+        assert!(!top_level.benchmark_sets.contains_key("old"));
+        assert_eq!(top_level.meta_sets["parent"], members(&["new", "other"]));
+    }
+
+    #[test]
+    fn rename_keeps_references_to_a_meta_sharing_the_old_name() {
+        let file = TempFile::new("sets.json");
+        let mut top_level = TopLevel::default();
+        top_level.benchmark_sets.insert("old".to_string(), set(100, 1));
+        top_level.benchmark_sets.insert("child".to_string(), set(100, 1));
+        top_level.meta_sets.insert("old".to_string(), members(&["child"]));
+        top_level.meta_sets.insert("parent".to_string(), members(&["old"]));
+        write_top_level_to_file(&top_level, &file.path).unwrap();
+
+        rename_benchmark_set("old", "new", &file.path).unwrap();
+        let top_level = load_top_level_from_file(&file.path).unwrap();
+        assert_eq!(top_level.meta_sets["parent"], members(&["new", "old"]));
+        let resolved: BTreeSet<String> = top_level.resolve_meta("parent").into_keys().collect();
+        assert_eq!(resolved, members(&["child", "new"]));
+    }
+
+    /// A few thousand meta sets, each listing its own benchmark set and the next ten
+    /// meta sets around a ring, so every set is reachable from every meta set through
+    /// many paths, which is quadratic with a linear scan of the seen keys.
+    #[test]
+    fn meta_walk_handles_a_large_interlinked_graph() {
+        let count = 5000;
+        let mut top_level = TopLevel::default();
+        for i in 0..count {
+            top_level.benchmark_sets.insert(format!("set-{}", i), set(100, 1));
+            let mut members: BTreeSet<String> = (1..=10).map(|step| format!("meta-{}", (i + step) % count)).collect();
+            members.insert(format!("set-{}", i));
+            top_level.meta_sets.insert(format!("meta-{}", i), members);
+        }
+        let started = std::time::Instant::now();
+        assert_eq!(walk_meta_for_benchmarks("meta-0", &top_level).len(), count);
+        assert_eq!(walk_meta_for_metas("meta-0", &top_level).len(), count);
+        assert!(started.elapsed() < std::time::Duration::from_secs(10), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn meta_walk_handles_a_deep_chain() {
+        let depth = 100_000;
+        let mut top_level = TopLevel::default();
+        for i in 0..depth {
+            top_level.meta_sets.insert(format!("meta-{}", i), members(&[&format!("meta-{}", i + 1)]));
+        }
+        top_level.meta_sets.insert(format!("meta-{}", depth), members(&["leaf"]));
+        top_level.benchmark_sets.insert("leaf".to_string(), set(100, 1));
+        assert_eq!(walk_meta_for_benchmarks("meta-0", &top_level).len(), 1);
+        assert_eq!(walk_meta_for_metas("meta-0", &top_level).len(), depth + 1);
+    }
+
+    fn orphan_fixture(file: &Path) {
+        let mut top_level = TopLevel::default();
+        for name in &["used", "stale", "loose"] {
+            top_level.benchmark_sets.insert(name.to_string(), set(100, 1));
+        }
+        top_level.meta_sets.insert("all".to_string(), members(&["used"]));
+        top_level.meta_sets.insert("old".to_string(), members(&["stale"]));
+        write_top_level_to_file(&top_level, file).unwrap();
+    }
+
+    #[test]
+    fn orphan_sets_count_every_meta_as_an_entry_point() {
+        let file = TempFile::new("sets.json");
+        orphan_fixture(&file.path);
+        assert_eq!(find_orphan_sets(&file.path).unwrap(), ["loose"]);
+    }
+
+    #[test]
+    fn orphan_sets_from_roots_include_sets_of_orphaned_metas() {
+        let file = TempFile::new("sets.json");
+        orphan_fixture(&file.path);
+        assert_eq!(find_orphan_sets_from(&["all"], &file.path).unwrap(), ["loose", "stale"]);
+        assert_eq!(find_orphan_sets_from(&["all", "old"], &file.path).unwrap(), ["loose"]);
+        assert_eq!(find_orphan_sets_from(&["used"], &file.path).unwrap(), ["loose", "stale", "used"]);
+    }
+
+    #[test]
+    fn concurrent_writes_in_one_process_use_separate_temporary_files() {
+        let file = TempFile::new("sets.json");
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let path = file.path.clone();
+                std::thread::spawn(move || {
+                    let mut top_level = TopLevel::default();
+                    top_level.benchmark_sets.insert(format!("set-{}", i), set(100, 1));
+                    for _ in 0..20 {
+                        write_top_level_to_file(&top_level, &path).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(load_top_level_from_file(&file.path).unwrap().benchmark_sets.len(), 1);
+        assert_eq!(std::fs::read_dir(&file.dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn backups_taken_in_quick_succession_are_all_distinct() {
+        let file = TempFile::new("sets.json");
+        for i in 0..MAX_BACKUPS + 3 {
+            write_benchmark_set_with_backup(&format!("set-{}", i), set(100, 1), ProcedureOverwrite::False, &file.path)
+                .unwrap();
+        }
+        let mut backups: Vec<PathBuf> = std::fs::read_dir(&file.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bak"))
+            .collect();
+        backups.sort();
+        // The backup taken before write i holds the i sets written so far, so the
+        // newest ones hold the most.
+        let counts: Vec<usize> = backups
+            .iter()
+            .map(|path| load_top_level_from_file(path).unwrap().benchmark_sets.len())
+            .collect();
+        assert_eq!(counts, (3..MAX_BACKUPS + 3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn meta_totals_reject_a_missing_meta() {
+        let file = TempFile::new("sets.json");
+        orphan_fixture(&file.path);
+        assert_eq!(meta_disk_size("all", &file.path), Ok(0));
+        assert_eq!(meta_disk_size("nope", &file.path), Err(ProcedureError::SetNotPresent));
+        assert_eq!(unique_maps_in_meta("nope", &file.path), Err(ProcedureError::SetNotPresent));
+        assert_eq!(unique_mods_in_meta("nope", &file.path), Err(ProcedureError::SetNotPresent));
+        assert_eq!(total_runs_for_meta("nope", &file.path), Err(ProcedureError::SetNotPresent));
+        assert_eq!(total_ticks_for_meta("nope", &file.path), Err(ProcedureError::SetNotPresent));
+    }
+
+    #[test]
+    fn meta_set_operations_reject_a_missing_meta() {
+        let file = TempFile::new("sets.json");
+        orphan_fixture(&file.path);
+        assert_eq!(meta_difference("all", "old", &file.path).unwrap(), members(&["used"]));
+        assert_eq!(meta_intersection("nope", "all", &file.path), Err(ProcedureError::SetNotPresent));
+        assert_eq!(meta_difference("all", "nope", &file.path), Err(ProcedureError::SetNotPresent));
+    }
+
+    #[test]
+    fn diff_reports_created_and_unknown_keys() {
+        let mut old = TopLevel::default();
+        old.benchmark_sets.insert("a".to_string(), set(100, 1));
+        let mut new = old.clone();
+        let changed_set = new.benchmark_sets.get_mut("a").unwrap();
+        changed_set.created = Some("2024-01-01T00:00:00Z".to_string());
+        changed_set.extra.insert("note".to_string(), serde_json::Value::from("kept"));
+
+        let delta = &diff(&old, &new).changed_benchmark_sets["a"];
+        assert_eq!(delta.created, Some((None, Some("2024-01-01T00:00:00Z".to_string()))));
+        assert_eq!(delta.extra.as_ref().map(|(old, new)| (old.len(), new.len())), Some((0, 1)));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_write_failure_carries_the_reason() {
+        let file = TempFile::new("sets.toml");
+        let mut top_level = TopLevel::default();
+        let mut with_null = set(100, 1);
+        with_null.extra.insert("note".to_string(), serde_json::Value::Null);
+        top_level.benchmark_sets.insert("a".to_string(), with_null);
+        match write_top_level_to_file(&top_level, &file.path) {
+            Err(ProcedureError::SerializationFailed(reason)) => assert!(!reason.is_empty()),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn hashless_identical_mods_dedupe_in_a_set() {
+        let mods: BTreeSet<Mod> = vec![
+            Mod::new("rso-mod", "rso-mod_6.2.5.zip", "6.2.5", ""),
+            Mod::new("rso-mod", "rso-mod_6.2.5.zip", "6.2.5", ""),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(mods.len(), 1);
+        assert_eq!(
+            Mod::new("rso-mod", "rso-mod_6.2.5.zip", "6.2.5", ""),
+            Mod::new("rso-mod", "rso-mod_6.2.5.zip", "6.2.5", "")
+        );
+    }
+
+    #[test]
+    fn mods_order_versions_numerically() {
+        let mods: BTreeSet<Mod> = vec!["1.9.0", "1.10.0", "1.2.3"]
+            .into_iter()
+            .map(|version| Mod::new("rso-mod", &format!("rso-mod_{}.zip", version), version, ""))
+            .collect();
+        let versions: Vec<&str> = mods.iter().map(|m| m.version.as_str()).collect();
+        assert_eq!(versions, ["1.2.3", "1.9.0", "1.10.0"]);
+        assert_eq!(mods.iter().last().unwrap().version, "1.10.0");
+
+        let mods: BTreeSet<Mod> = vec!["1.9", "1.2x", "1.10", "beta", "1.02", "1.2"]
+            .into_iter()
+            .map(|version| Mod::new("rso-mod", &format!("rso-mod_{}.zip", version), version, ""))
+            .collect();
+        let versions: Vec<&str> = mods.iter().map(|m| m.version.as_str()).collect();
+        assert_eq!(versions, ["1.02", "1.2", "1.9", "1.10", "1.2x", "beta"]);
+    }
+
+    #[test]
+    fn scaled_runs_ignores_float_error_in_exact_products() {
+        assert_eq!(scaled_runs(100, 1.1), 110);
+        assert_eq!(scaled_runs(50, 1.1), 55);
+        assert_eq!(scaled_runs(10, 2.2), 22);
+        assert_eq!(scaled_runs(100, 2.2), 220);
+        assert_eq!(scaled_runs(3, 0.1), 1);
+        assert_eq!(scaled_runs(30, 0.1), 3);
+    }
+
+    #[test]
+    fn scaled_runs_rounds_up_inexact_products() {
+        assert_eq!(scaled_runs(3, 1.5), 5);
+        assert_eq!(scaled_runs(7, 0.5), 4);
+        assert_eq!(scaled_runs(10, 1.01), 11);
+        assert_eq!(scaled_runs(5, 0.0), 1);
+        assert_eq!(scaled_runs(5, -2.0), 1);
+        assert_eq!(scaled_runs(5, f64::NAN), 1);
     }
 }